@@ -1,4 +1,6 @@
 use crate::operator::OperatorDefinition;
+use crate::span::{ Span, Spanned };
+use num_bigint::BigInt;
 
 // Should I just add the short circuited things to the language
 // rather than setting up a whole system for it?
@@ -9,16 +11,120 @@ use crate::operator::OperatorDefinition;
 // Or just have it not short circuit initially and add it after
 // basic language stuff.
 
+/// The parts of an expression are Spanned so every node knows where it is in the source.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     Ident(String),
-    Number(f64),
+    Integer(BigInt),
+    Float(f64),
     Str(String),
-    Function(Vec<String>, Box<Expression>),
-    Call(Box<Expression>, Vec<Expression>),
-    Prefix(OperatorDefinition, Box<Expression>),
-    Infix(Box<Expression>, OperatorDefinition, Box<Expression>),
-    Postfix(Box<Expression>, OperatorDefinition),
-    // TODO Correct
-    IndexMap(Vec<Expression>),
-    If(Box<Expression>, Box<Expression>, Option<Box<Expression>>),
+    /// The parts of an interpolated string, joined together using their Display.
+    Interpolation(Vec<Spanned<Expression>>),
+    Symbol(String),
+    Bool(bool),
+    Nil,
+    /// Parameters, body and doc comment.
+    Function(Vec<String>, Box<Spanned<Expression>>, Option<String>),
+    Call(Box<Spanned<Expression>>, Vec<Spanned<Expression>>),
+    Prefix(OperatorDefinition, Box<Spanned<Expression>>),
+    Infix(Box<Spanned<Expression>>, OperatorDefinition, Box<Spanned<Expression>>),
+    Postfix(Box<Spanned<Expression>>, OperatorDefinition),
+    /// Entries without a key are given the next position as their key.
+    IndexMap(Vec<(Option<Spanned<Expression>>, Spanned<Expression>)>),
+    Index(Box<Spanned<Expression>>, Box<Spanned<Expression>>),
+    /// Like Index, but gives nil for missing keys or a nil left side
+    /// instead of an error.
+    SafeIndex(Box<Spanned<Expression>>, Box<Spanned<Expression>>),
+    If(Box<Spanned<Expression>>, Box<Spanned<Expression>>, Option<Box<Spanned<Expression>>>),
+    /// The doc comment is given to the value if it's a function without one.
+    Let(String, Box<Spanned<Expression>>, Box<Spanned<Expression>>, Option<String>),
+    /// Expressions run in order, separated by newlines or semicolons. Gives the value of the last one.
+    Block(Vec<Spanned<Expression>>),
+    /// [value for x in xs if cond], or [key: value for ...] to choose the keys.
+    Comprehension(Option<Box<Spanned<Expression>>>, Box<Spanned<Expression>>, Vec<Clause>),
+    /// try body catch name handler.
+    Try(Box<Spanned<Expression>>, String, Box<Spanned<Expression>>),
+    /// Makes the function it's in a generator.
+    Yield(Box<Spanned<Expression>>),
+    /// yield from, which yields every value of an iterable.
+    YieldFrom(Box<Spanned<Expression>>),
+    /// Stands in for code that failed to parse, so the parser can carry on and find more errors.
+    Error,
+}
+
+/// The parts of a comprehension after the value, each applying to the ones after it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Clause {
+    /// for name in iterable, or for a, b in iterable to unpack each value.
+    For(Vec<String>, Spanned<Expression>),
+    If(Spanned<Expression>),
+}
+
+impl Expression {
+    /// Whether there's a yield in this expression, not counting ones in functions inside it.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            Expression::Yield(_) | Expression::YieldFrom(_) => true,
+            Expression::Function(..) => false,
+            Expression::Interpolation(parts) | Expression::Block(parts) => parts.iter().any(|part| part.contains_yield()),
+            Expression::Call(function, args) => function.contains_yield() || args.iter().any(|arg| arg.contains_yield()),
+            Expression::Prefix(_, right) => right.contains_yield(),
+            Expression::Postfix(left, _) => left.contains_yield(),
+            Expression::Infix(left, _, right)
+                | Expression::Index(left, right)
+                | Expression::SafeIndex(left, right) => left.contains_yield() || right.contains_yield(),
+            Expression::IndexMap(entries) => entries.iter().any(|(key, value)| {
+                key.as_ref().map(|key| key.contains_yield()).unwrap_or(false) || value.contains_yield()
+            }),
+            Expression::If(cond, cons, alt) => cond.contains_yield()
+                || cons.contains_yield()
+                || alt.as_ref().map(|alt| alt.contains_yield()).unwrap_or(false),
+            Expression::Let(_, value, body, _) => value.contains_yield() || body.contains_yield(),
+            Expression::Try(body, _, handler) => body.contains_yield() || handler.contains_yield(),
+            Expression::Comprehension(key, value, clauses) => {
+                key.as_ref().map(|key| key.contains_yield()).unwrap_or(false)
+                    || value.contains_yield()
+                    || clauses.iter().any(|clause| match clause {
+                        Clause::For(_, exp) | Clause::If(exp) => exp.contains_yield(),
+                    })
+            },
+            _ => false,
+        }
+    }
+
+    /// Where the first yield inside this expression is, not counting ones in functions inside it.
+    pub fn yield_span(&self) -> Option<Span> {
+        match self {
+            Expression::Yield(value) | Expression::YieldFrom(value) => first_yield(value),
+            Expression::Function(..) => None,
+            Expression::Interpolation(parts) | Expression::Block(parts) => parts.iter().find_map(first_yield),
+            Expression::Call(function, args) => first_yield(function).or_else(|| args.iter().find_map(first_yield)),
+            Expression::Prefix(_, right) => first_yield(right),
+            Expression::Postfix(left, _) => first_yield(left),
+            Expression::Infix(left, _, right)
+                | Expression::Index(left, right)
+                | Expression::SafeIndex(left, right) => first_yield(left).or_else(|| first_yield(right)),
+            Expression::IndexMap(entries) => entries.iter().find_map(|(key, value)| {
+                key.as_ref().and_then(first_yield).or_else(|| first_yield(value))
+            }),
+            Expression::If(cond, cons, alt) => first_yield(cond)
+                .or_else(|| first_yield(cons))
+                .or_else(|| alt.as_deref().and_then(first_yield)),
+            Expression::Let(_, value, body, _) => first_yield(value).or_else(|| first_yield(body)),
+            Expression::Try(body, _, handler) => first_yield(body).or_else(|| first_yield(handler)),
+            Expression::Comprehension(key, value, clauses) => key.as_deref().and_then(first_yield)
+                .or_else(|| first_yield(value))
+                .or_else(|| clauses.iter().find_map(|clause| match clause {
+                    Clause::For(_, exp) | Clause::If(exp) => first_yield(exp),
+                })),
+            _ => None,
+        }
+    }
+}
+
+fn first_yield(exp: &Spanned<Expression>) -> Option<Span> {
+    match exp.node {
+        Expression::Yield(_) | Expression::YieldFrom(_) => Some(exp.span),
+        ref node => node.yield_span(),
+    }
 }
@@ -0,0 +1,337 @@
+use crate::object::{ Object, Builtin, Function };
+use crate::ast::Expression;
+use crate::span::Spanned;
+use crate::environment::Environment;
+use crate::operator::{ OperatorDefinition, OperatorType, Precedence };
+use crate::error::{ ErrorKind, RoughError, RoughResult, new_error, raise_error };
+use crate::number;
+use crate::range;
+use crate::iterator::{ self, Iter, ObjectIter };
+use indexmap::IndexMap;
+use std::rc::Rc;
+
+pub fn builtins() -> Vec<Builtin> {
+    vec![
+        Builtin { name: "print", func: print, doc: "Prints the arguments separated by spaces." },
+        Builtin { name: "len", func: len, doc: "The number of characters in a string, entries in an IndexMap or values in a range." },
+        Builtin { name: "sort", func: sort, doc: "The values of an IndexMap in order, with new positional keys." },
+        Builtin { name: "sort_keys", func: sort_keys, doc: "An IndexMap with its entries put in key order." },
+        Builtin { name: "rational", func: rational, doc: "The exact fraction numerator / denominator." },
+        Builtin { name: "int", func: int, doc: "Converts a number to an integer, rounding towards zero." },
+        Builtin { name: "float", func: float, doc: "Converts a number to a float." },
+        Builtin { name: "step", func: step, doc: "The range with every nth value, going backwards if n is negative." },
+        Builtin { name: "iter", func: iter, doc: "An iterator over the values of an IndexMap, string or range." },
+        Builtin { name: "lines", func: lines, doc: "An iterator over the lines of a file, read as they're needed." },
+        Builtin { name: "map", func: map, doc: "Lazily calls a function on each value." },
+        Builtin { name: "filter", func: filter, doc: "Lazily keeps the values a function gives a truthy result for." },
+        Builtin { name: "take", func: take, doc: "Lazily gives the first n values." },
+        Builtin { name: "zip", func: zip, doc: "Lazily pairs up the values of two things, stopping at the shorter." },
+        Builtin { name: "enumerate", func: enumerate, doc: "Lazily pairs each value with its position." },
+        Builtin { name: "chain", func: chain, doc: "Lazily gives the values of each argument in turn." },
+        Builtin { name: "fold", func: fold, doc: "Combines the values using a function of the total so far and the next value." },
+        Builtin { name: "collect", func: collect, doc: "Puts the values of anything iterable in an IndexMap with positional keys." },
+        Builtin { name: "raise", func: raise, doc: "Raises an error: raise(message), raise('kind, message, [payload]) or raise(caught_error)." },
+        Builtin { name: "help", func: help, doc: "Prints the documentation of a function." },
+    ]
+}
+
+/// The operators every program starts with.
+pub fn operators() -> Vec<OperatorDefinition> {
+    let infix = |identifier: &str, precedence: Precedence| OperatorDefinition {
+        identifier: identifier.to_string(),
+        op_type: OperatorType::Infix,
+        precedence,
+    };
+    let prefix = |identifier: &str| OperatorDefinition {
+        identifier: identifier.to_string(),
+        op_type: OperatorType::Prefix,
+        precedence: Precedence::Ninth,
+    };
+
+    vec![
+        infix("==", Precedence::Third),
+        infix("!=", Precedence::Third),
+        infix("≠", Precedence::Third),
+        infix("<", Precedence::Fourth),
+        infix(">", Precedence::Fourth),
+        infix("<=", Precedence::Fourth),
+        infix(">=", Precedence::Fourth),
+        infix("≤", Precedence::Fourth),
+        infix("≥", Precedence::Fourth),
+        infix("..", Precedence::Fifth),
+        infix("..=", Precedence::Fifth),
+        infix("+", Precedence::Sixth),
+        infix("-", Precedence::Sixth),
+        infix("*", Precedence::Seventh),
+        infix("/", Precedence::Seventh),
+        infix("//", Precedence::Seventh),
+        infix("%", Precedence::Seventh),
+        infix("∘", Precedence::Eighth),
+        prefix("-"),
+        prefix("!"),
+    ]
+}
+
+pub fn eval_prefix(op: &str, right: Object) -> RoughResult<Object> {
+    match (op, right) {
+        ("-", right) => number::negate(right),
+        ("!", right) => Ok(Object::Bool(!right.is_truthy())),
+        (op, right) => new_error(ErrorKind::Type, format!("Prefix operator {} can't be used on {}", op, right.type_name())),
+    }
+}
+
+pub fn eval_infix(left: Object, op: &str, right: Object) -> RoughResult<Object> {
+    let op = match op {
+        "≠" => "!=",
+        "≤" => "<=",
+        "≥" => ">=",
+        "∘" => return compose(left, right),
+        ".." => return range::make_range(&left, &right, false),
+        "..=" => return range::make_range(&left, &right, true),
+        op => op,
+    };
+
+    // Comparisons work on any two values using Object's ordering.
+    match op {
+        "==" => return Ok(Object::Bool(left == right)),
+        "!=" => return Ok(Object::Bool(left != right)),
+        "<" => return Ok(Object::Bool(left < right)),
+        ">" => return Ok(Object::Bool(left > right)),
+        "<=" => return Ok(Object::Bool(left <= right)),
+        ">=" => return Ok(Object::Bool(left >= right)),
+        _ => (),
+    }
+
+    match (left, right) {
+        (l, r) if number::is_number(&l) && number::is_number(&r) => number::arithmetic(&l, op, &r),
+        (Object::Str(l), Object::Str(r)) => match op {
+            "+" => Ok(Object::Str(l + &r)),
+            _ => new_error(ErrorKind::Type, format!("Operator {} isn't defined for Strings", op)),
+        },
+        (left, right) => new_error(ErrorKind::Type, format!(
+                "Operator {} can't be used on {} and {}",
+                op,
+                left.type_name(),
+                right.type_name()
+                )),
+    }
+}
+
+/// f ∘ g is a function that calls g, then calls f on the result.
+fn compose(f: Object, g: Object) -> RoughResult<Object> {
+    for obj in [&f, &g].iter() {
+        if !matches!(obj, Object::Function(_) | Object::Builtin(_)) {
+            return new_error(ErrorKind::Type, format!("Operator ∘ can only compose functions, not {}", obj.type_name()));
+        }
+    }
+
+    let env = Environment::new();
+    env.borrow_mut().set("f".to_string(), f);
+    env.borrow_mut().set("g".to_string(), g);
+
+    // There's no source for these calls, so stack traces show them at 0.
+    let call = |name: &str, arg: Spanned<Expression>| Spanned::unspanned(Expression::Call(
+        Box::new(Spanned::unspanned(Expression::Ident(name.to_string()))),
+        vec![arg]
+        ));
+
+    Ok(Object::Function(Rc::new(Function {
+        params: vec!["x".to_string()],
+        body: call("f", call("g", Spanned::unspanned(Expression::Ident("x".to_string())))),
+        env,
+        doc: None,
+        is_generator: false,
+        name: Some("∘".to_string()),
+        position: 0,
+    })))
+}
+
+fn print(args: Vec<Object>) -> RoughResult<Object> {
+    let strings: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    println!("{}", strings.join(" "));
+    Ok(Object::Nil)
+}
+
+/// The text shown by help, which is also used by the REPL.
+pub fn help_text(obj: &Object) -> String {
+    match obj {
+        Object::Function(function) => format!(
+            "|{}|\n{}",
+            function.params.join(", "),
+            function.doc.as_deref().unwrap_or("No documentation.")
+            ),
+        Object::Builtin(builtin) => format!("{} (builtin)\n{}", builtin.name, builtin.doc),
+        other => format!("{} has no documentation.", other.type_name()),
+    }
+}
+
+fn help(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [obj] => {
+            println!("{}", help_text(obj));
+            Ok(Object::Nil)
+        },
+        _ => new_error(ErrorKind::Arity, format!("help expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn len(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [Object::Str(string)] => Ok(Object::Integer(string.chars().count() as i64)),
+        [Object::IndexMap(map)] => Ok(Object::Integer(map.len() as i64)),
+        [Object::Range(range)] => Ok(number::from_big(range.len().into())),
+        [other] => new_error(ErrorKind::Type, format!("len can't be used on {}", other.type_name())),
+        _ => new_error(ErrorKind::Arity, format!("len expects 1 argument but got {}", args.len())),
+    }
+}
+
+/// Gives the values in order with new positional keys.
+fn sort(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [Object::IndexMap(map)] => {
+            let mut values: Vec<Object> = map.values().cloned().collect();
+            values.sort();
+            Ok(Object::IndexMap(values.into_iter()
+                    .enumerate()
+                    .map(|(i, value)| (Object::Integer(i as i64), value))
+                    .collect()))
+        },
+        [other] => new_error(ErrorKind::Type, format!("sort can't be used on {}", other.type_name())),
+        _ => new_error(ErrorKind::Arity, format!("sort expects 1 argument but got {}", args.len())),
+    }
+}
+
+/// Keeps each key with its value, but puts the entries in key order.
+fn sort_keys(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [Object::IndexMap(map)] => {
+            let mut sorted: IndexMap<Object, Object> = map.clone();
+            sorted.sort_keys();
+            Ok(Object::IndexMap(sorted))
+        },
+        [other] => new_error(ErrorKind::Type, format!("sort_keys can't be used on {}", other.type_name())),
+        _ => new_error(ErrorKind::Arity, format!("sort_keys expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn step(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [Object::Range(range), Object::Integer(step)] => Ok(Object::Range(range.with_step(*step)?)),
+        [Object::Range(_), other] => new_error(ErrorKind::Type, format!("step needs an Integer step, not {}", other.type_name())),
+        [other, _] => new_error(ErrorKind::Type, format!("step can't be used on {}", other.type_name())),
+        _ => new_error(ErrorKind::Arity, format!("step expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn iterator(iter: ObjectIter) -> RoughResult<Object> {
+    Ok(Object::Iterator(Iter::new(iter)))
+}
+
+fn iter(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [obj] => iterator(iterator::iterate(obj.clone())?),
+        _ => new_error(ErrorKind::Arity, format!("iter expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn lines(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [Object::Str(path)] => iterator(iterator::lines(path)?),
+        [other] => new_error(ErrorKind::Type, format!("lines needs a path String, not {}", other.type_name())),
+        _ => new_error(ErrorKind::Arity, format!("lines expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn map(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values, function] => iterator(iterator::map(iterator::iterate(values.clone())?, function.clone())),
+        _ => new_error(ErrorKind::Arity, format!("map expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn filter(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values, predicate] => iterator(iterator::filter(iterator::iterate(values.clone())?, predicate.clone())),
+        _ => new_error(ErrorKind::Arity, format!("filter expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn take(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values, Object::Integer(n)] if *n >= 0 => iterator(Box::new(iterator::iterate(values.clone())?.take(*n as usize))),
+        [_, n] => new_error(ErrorKind::Value, format!("take needs a count that's a positive Integer, not {}", n)),
+        _ => new_error(ErrorKind::Arity, format!("take expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn zip(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [left, right] => iterator(iterator::zip(iterator::iterate(left.clone())?, iterator::iterate(right.clone())?)),
+        _ => new_error(ErrorKind::Arity, format!("zip expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn enumerate(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values] => iterator(iterator::enumerate(iterator::iterate(values.clone())?)),
+        _ => new_error(ErrorKind::Arity, format!("enumerate expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn chain(args: Vec<Object>) -> RoughResult<Object> {
+    let iters = args.into_iter()
+        .map(iterator::iterate)
+        .collect::<RoughResult<Vec<ObjectIter>>>()?;
+    iterator(iterator::chain(iters))
+}
+
+fn fold(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values, init, function] => iterator::fold(iterator::iterate(values.clone())?, init.clone(), function.clone()),
+        _ => new_error(ErrorKind::Arity, format!("fold expects 3 arguments but got {}", args.len())),
+    }
+}
+
+fn collect(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [values] => iterator::collect(iterator::iterate(values.clone())?),
+        _ => new_error(ErrorKind::Arity, format!("collect expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn raise(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [error @ Object::Error(_)] => Err(vec![RoughError::raised(ErrorKind::Raised, error.clone())]),
+        [Object::Str(message)] => raise_error(ErrorKind::Raised, ErrorKind::Raised.symbol(), message.clone(), IndexMap::new()),
+        [Object::Symbol(kind), Object::Str(message)] => raise_error(ErrorKind::Raised, kind, message.clone(), IndexMap::new()),
+        [Object::Symbol(kind), Object::Str(message), Object::IndexMap(payload)] => raise_error(ErrorKind::Raised, kind, message.clone(), payload.clone()),
+        _ => new_error(ErrorKind::Arity, format!(
+                "raise expects a message, or a kind symbol, message and maybe a payload IndexMap, but got {}",
+                args.iter().map(Object::type_name).collect::<Vec<&str>>().join(", ")
+                )),
+    }
+}
+
+fn rational(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [numer, denom] => number::make_rational(numer, denom),
+        _ => new_error(ErrorKind::Arity, format!("rational expects 2 arguments but got {}", args.len())),
+    }
+}
+
+fn int(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [obj] => number::to_integer(obj),
+        _ => new_error(ErrorKind::Arity, format!("int expects 1 argument but got {}", args.len())),
+    }
+}
+
+fn float(args: Vec<Object>) -> RoughResult<Object> {
+    match args.as_slice() {
+        [obj] => match number::to_float(obj) {
+            Some(float) => Ok(Object::Float(float)),
+            None => new_error(ErrorKind::Type, format!("float can't be used on {}", obj.type_name())),
+        },
+        _ => new_error(ErrorKind::Arity, format!("float expects 1 argument but got {}", args.len())),
+    }
+}
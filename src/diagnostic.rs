@@ -0,0 +1,124 @@
+use crate::error::RoughError;
+use crate::source_map::SourceMap;
+use crate::span::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+/// Shows errors with where they happened, like
+///
+/// ```text
+/// Error[E0003]: Identifier ln not found
+///  --> main.rough:1:10
+///   |
+/// 1 | f := |x| len(ln) in f(1)
+///   |              ^^
+///   = help: did you mean len?
+/// ```
+///
+/// along with a traceback if the error went through any function calls.
+/// Each of the error's labels gets a snippet of its own, underlined with
+/// dashes instead of carets.
+pub struct Renderer<'a> {
+    sources: &'a SourceMap,
+    /// Whether to use terminal colour codes.
+    colour: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(sources: &'a SourceMap, colour: bool) -> Renderer<'a> {
+        Renderer {
+            sources,
+            colour,
+        }
+    }
+
+    pub fn render(&self, error: &RoughError) -> String {
+        let mut output = error.traceback_with(|frame| frame.describe(|position| self.sources.describe(position)));
+        output.push_str(&format!("{}: {}\n", self.paint(RED, &format!("Error[{}]", error.code())), error));
+
+        let primary = error.span().into_iter().map(|span| (span, None));
+        let labels = error.labels().iter().map(|label| (label.span, Some(label.message.as_str())));
+        let spans: Vec<(Span, Option<&str>)> = primary.chain(labels).collect();
+
+        // Every snippet has the same gutter, wide enough for the longest line number.
+        let width = spans.iter()
+            .filter_map(|(span, _)| self.sources.location(span.start))
+            .map(|location| location.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        for (span, label) in spans {
+            if let Some(snippet) = self.snippet(span, label, width) {
+                output.push_str(&snippet);
+            }
+        }
+
+        let gutter = " ".repeat(width);
+        for note in error.notes() {
+            output.push_str(&format!("{} {} note: {}\n", gutter, self.paint(BLUE, "="), note));
+        }
+        if let Some(help) = error.help() {
+            output.push_str(&format!("{} {} help: {}\n", gutter, self.paint(BLUE, "="), help));
+        }
+
+        output
+    }
+
+    pub fn render_all(&self, errors: &[RoughError]) -> String {
+        errors.iter().map(|error| self.render(error)).collect()
+    }
+
+    /// The line the span starts on with the span underlined. A span that
+    /// goes over more than one line is underlined to the end of its first line.
+    /// The error's own span gets carets, and labels get dashes and their message.
+    fn snippet(&self, span: Span, label: Option<&str>, width: usize) -> Option<String> {
+        let (start, end) = (span.start, span.end);
+        let file = self.sources.file(start)?;
+        let location = self.sources.location(start)?;
+        let text = file.line(location.line);
+
+        let chars_before = location.column - 1;
+        let (start, end) = (start - file.start, end.min(file.start + file.source.len()) - file.start);
+        let underlined = file.source.get(start..end.max(start))
+            .map(|span_text| span_text.chars().take_while(|ch| *ch != '\n').count())
+            .unwrap_or(0)
+            .min(text.chars().count().saturating_sub(chars_before))
+            .max(1);
+
+        // Tabs are kept so the carets line up however wide the terminal shows them.
+        let indent: String = text.chars()
+            .take(chars_before)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let line_number = format!("{:<width$}", location.line, width = width);
+        let gutter = " ".repeat(width);
+
+        let (arrow, underline) = match label {
+            None => (self.paint(BLUE, "-->"), self.paint(RED, &"^".repeat(underlined))),
+            Some(message) => (self.paint(BLUE, ":::"), self.paint(BLUE, &format!("{} {}", "-".repeat(underlined), message))),
+        };
+
+        Some(format!(
+                "{gutter}{arrow} {location}\n{gutter} {bar}\n{number} {bar} {text}\n{gutter} {bar} {indent}{underline}\n",
+                gutter = gutter,
+                arrow = arrow,
+                location = location,
+                bar = self.paint(BLUE, "|"),
+                number = self.paint(BLUE, &line_number),
+                text = text,
+                indent = indent,
+                underline = underline,
+                ))
+    }
+
+    fn paint(&self, colour: &str, text: &str) -> String {
+        if self.colour {
+            format!("{}{}{}", colour, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::object::Object;
+use crate::builtin::builtins;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type Env = Rc<RefCell<Environment>>;
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Environment::default()))
+    }
+
+    /// The top level environment with all of the builtin functions.
+    pub fn global() -> Env {
+        let env = Environment::new();
+
+        for builtin in builtins() {
+            env.borrow_mut().set(builtin.name.to_string(), Object::Builtin(builtin));
+        }
+
+        env
+    }
+
+    pub fn new_enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(Rc::clone(outer)),
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.borrow().get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: String, value: Object) {
+        self.store.insert(name, value);
+    }
+
+    /// The name in scope that's closest to name, if any is close enough to be a typo of it.
+    pub fn similar_name(&self, name: &str) -> Option<String> {
+        let allowed = (name.chars().count() / 3).max(1);
+        let mut names = vec![];
+        self.collect_names(&mut names);
+
+        names.into_iter()
+            .map(|candidate| (edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= allowed)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+
+    fn collect_names(&self, names: &mut Vec<String>) {
+        names.extend(self.store.keys().cloned());
+        if let Some(outer) = &self.outer {
+            outer.borrow().collect_names(names);
+        }
+    }
+}
+
+/// How many characters have to be added, removed or changed to turn one string into the other.
+fn edit_distance(from: &str, to: &str) -> usize {
+    let to: Vec<char> = to.chars().collect();
+    let mut previous: Vec<usize> = (0..=to.len()).collect();
+
+    for (i, from_char) in from.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, to_char) in to.iter().enumerate() {
+            let change = previous[j] + if from_char == *to_char { 0 } else { 1 };
+            current.push(change.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[to.len()]
+}
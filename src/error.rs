@@ -1,21 +1,272 @@
+use crate::object::{ Object, ErrorValue };
+use crate::span::Span;
+use indexmap::IndexMap;
+use std::fmt;
+use std::rc::Rc;
 
 /// For functionality regarding handling and displaying
 /// errors in the Rough code.
 /// Note: this isn't about errors in the interpreter,
 /// that is handled differently.
-
-// This might change quite a bit.
-// Might have more than one error type.
-// Might have a list of messages, errors, etc.
+///
+/// Besides the message, an error can point at other parts of the code with
+/// labels, and give notes and a suggestion for fixing it, which are all
+/// shown by the diagnostic Renderer.
 #[derive(Debug, PartialEq, Clone)]
 pub struct RoughError {
+    kind: ErrorKind,
     msg: String,
+    /// The Rough error value, when the error was raised as one.
+    value: Option<Object>,
+    /// The calls the error went back through, innermost first.
+    trace: Vec<TraceFrame>,
+    /// Where in the source it happened, if that's known.
+    span: Option<Span>,
+    /// Other places in the source to do with the error, like where an unclosed ( was opened.
+    labels: Vec<Label>,
+    notes: Vec<String>,
+    help: Option<String>,
+}
+
+/// What sort of error it is, for programs using Rough to decide what to do about it.
+/// More kinds might be added, so matches on it need a catch-all arm.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Source that can't be made into tokens, like a bad escape or indentation.
+    Lex,
+    /// Tokens that don't make a valid expression.
+    Parse,
+    /// A name that isn't bound to anything.
+    Name,
+    /// A value of the wrong type, like adding a String to an Integer.
+    Type,
+    /// A function given the wrong number of arguments.
+    Arity,
+    /// A key that isn't in an IndexMap.
+    Index,
+    /// A value of the right type that still can't be used, like dividing by zero.
+    Value,
+    /// Reading a file failed.
+    Io,
+    /// Raised from Rough code with the raise builtin.
+    Raised,
+    /// Anything else that goes wrong while running.
+    Runtime,
+}
+
+impl ErrorKind {
+    /// A code for the kind that won't change, so it can be looked up or checked for.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::Lex => "E0001",
+            ErrorKind::Parse => "E0002",
+            ErrorKind::Name => "E0003",
+            ErrorKind::Type => "E0004",
+            ErrorKind::Arity => "E0005",
+            ErrorKind::Index => "E0006",
+            ErrorKind::Value => "E0007",
+            ErrorKind::Io => "E0008",
+            ErrorKind::Raised => "E0009",
+            ErrorKind::Runtime => "E0010",
+        }
+    }
+
+    /// The name of the symbol a catch sees as the error's kind, like 'type for a Type error.
+    /// raise without a kind uses 'error.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ErrorKind::Lex => "lex",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Name => "name",
+            ErrorKind::Type => "type",
+            ErrorKind::Arity => "arity",
+            ErrorKind::Index => "index",
+            ErrorKind::Value => "value",
+            ErrorKind::Io => "io",
+            ErrorKind::Raised => "error",
+            ErrorKind::Runtime => "runtime",
+        }
+    }
+}
+
+/// A secondary place in the source with a note on how it's involved in the error.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A function call an error happened inside of.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceFrame {
+    pub function: Callee,
+    /// Where it was called from. Functions called by builtins, like the one given
+    /// to map, don't have a call site of their own.
+    pub call_site: Option<usize>,
+}
+
+/// What a stack trace calls a function.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Callee {
+    /// A function bound to a name, or a builtin.
+    Named(String),
+    /// A function that's never been bound to a name, known by where it starts.
+    Lambda(usize),
+}
+
+impl TraceFrame {
+    /// Shows the frame with positions given by location, like turning them into lines and columns.
+    pub fn describe(&self, location: impl Fn(usize) -> String) -> String {
+        let function = match &self.function {
+            Callee::Named(name) => name.clone(),
+            Callee::Lambda(position) => format!("<lambda at {}>", location(*position)),
+        };
+
+        match self.call_site {
+            Some(position) => format!("{}, called at {}", function, location(position)),
+            None => format!("{}, called by a builtin", function),
+        }
+    }
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(|position| position.to_string()))
+    }
+}
+
+impl fmt::Display for RoughError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
 }
 
 impl RoughError {
-    pub fn new(message: String) -> RoughError {
+    pub fn new(kind: ErrorKind, message: String) -> RoughError {
         RoughError {
+            kind,
             msg: message,
+            value: None,
+            trace: vec![],
+            span: None,
+            labels: vec![],
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    /// An error made from a Rough error value, so catching it gives back the same value.
+    pub fn raised(kind: ErrorKind, value: Object) -> RoughError {
+        let msg = match &value {
+            Object::Error(error) => error.message.clone(),
+            other => other.to_string(),
+        };
+
+        RoughError {
+            value: Some(value),
+            ..RoughError::new(kind, msg)
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> RoughError {
+        self.labels.push(Label { span, message: message.to_string() });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> RoughError {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> RoughError {
+        self.help = Some(help);
+        self
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Sets where the error happened, unless something closer to it already has.
+    pub fn or_span(mut self, span: Span) -> RoughError {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    pub fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
+
+    /// Adds the call the error has just gone back out of.
+    pub fn push_frame(&mut self, frame: TraceFrame) {
+        self.trace.push(frame);
+    }
+
+    /// The calls the error happened in, innermost last like Python's.
+    /// Runs of the same frame, like from deep recursion, are only shown once
+    /// with a count of how many more there were. Empty if there were no calls.
+    pub fn traceback(&self) -> String {
+        self.traceback_with(|frame| frame.to_string())
+    }
+
+    /// Like traceback, with each frame shown by describe.
+    pub fn traceback_with(&self, describe: impl Fn(&TraceFrame) -> String) -> String {
+        if self.trace.is_empty() {
+            return String::new();
+        }
+
+        let mut lines = vec!["Traceback (most recent call last):".to_string()];
+        let mut frames = self.trace.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            lines.push(format!("  {}", describe(frame)));
+
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                let plural = if repeats == 1 { "" } else { "s" };
+                lines.push(format!("  [Previous line repeated {} more time{}]", repeats, plural));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// The value a catch gets. Errors that weren't raised from Rough have a kind from their ErrorKind.
+    pub fn to_value(&self) -> Object {
+        match &self.value {
+            Some(value) => value.clone(),
+            None => Object::Error(Rc::new(ErrorValue {
+                kind: self.kind.symbol().to_string(),
+                message: self.msg.clone(),
+                payload: IndexMap::new(),
+            })),
         }
     }
 }
@@ -23,6 +274,24 @@ impl RoughError {
 // Is handling multiple errors in the result better or in the lexer/parser/evaluators respectively?
 pub type RoughResult<T> = Result<T, Vec<RoughError>>;
 
-pub fn new_error<T>(msg: String) -> RoughResult<T> {
-    Err(vec![RoughError::new(msg)])
+pub fn new_error<T>(kind: ErrorKind, msg: String) -> RoughResult<T> {
+    Err(vec![RoughError::new(kind, msg)])
+}
+
+pub fn error_at<T>(kind: ErrorKind, msg: String, span: Span) -> RoughResult<T> {
+    Err(vec![RoughError::new(kind, msg).or_span(span)])
+}
+
+/// Gives a span to any of the errors that don't have one yet.
+pub fn locate(errors: Vec<RoughError>, span: Span) -> Vec<RoughError> {
+    errors.into_iter().map(|error| error.or_span(span)).collect()
+}
+
+/// An error that can be caught with a kind and payload, like a missing file being 'io.
+pub fn raise_error<T>(error_kind: ErrorKind, kind: &str, msg: String, payload: IndexMap<Object, Object>) -> RoughResult<T> {
+    Err(vec![RoughError::raised(error_kind, Object::Error(Rc::new(ErrorValue {
+        kind: kind.to_string(),
+        message: msg,
+        payload,
+    })))])
 }
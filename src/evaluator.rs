@@ -0,0 +1,624 @@
+use crate::ast::{ Expression, Clause };
+use crate::span::{ Span, Spanned };
+use crate::object::{ Object, Function };
+use crate::environment::{ Env, Environment };
+use crate::error::{ ErrorKind, RoughError, RoughResult, TraceFrame, Callee, new_error, error_at, locate };
+use crate::builtin::{ eval_infix, eval_prefix };
+use crate::number;
+use crate::range;
+use crate::iterator::{ self, Iter, ObjectIter };
+use indexmap::IndexMap;
+use std::cell::Cell;
+use std::mem;
+use std::rc::Rc;
+
+/// How deep function calls and iterators can go before giving an error, so runaway recursion
+/// is an error in Rough instead of overflowing the interpreter's own stack.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Calls that start this close to the end of the stack carry on in a new piece of
+/// stack, so reaching MAX_CALL_DEPTH works on any thread, whatever its stack size.
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// The size of each new piece of stack.
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Errors are given the span of the innermost expression they came from.
+pub fn evaluate(exp: &Spanned<Expression>, env: &Env) -> RoughResult<Object> {
+    eval_node(exp, env).map_err(|errors| locate(errors, exp.span))
+}
+
+fn eval_node(exp: &Spanned<Expression>, env: &Env) -> RoughResult<Object> {
+    match &exp.node {
+        Expression::Nil => Ok(Object::Nil),
+        Expression::Bool(boolean) => Ok(Object::Bool(*boolean)),
+        Expression::Integer(num) => Ok(number::from_big(num.clone())),
+        Expression::Float(num) => Ok(Object::Float(*num)),
+        Expression::Str(string) => Ok(Object::Str(string.clone())),
+        Expression::Interpolation(parts) => {
+            let mut string = String::new();
+            for part in parts {
+                string.push_str(&evaluate(part, env)?.to_string());
+            }
+            Ok(Object::Str(string))
+        },
+        Expression::Symbol(name) => Ok(Object::Symbol(name.clone())),
+        Expression::Ident(name) => match env.borrow().get(name) {
+            Some(obj) => Ok(obj),
+            None => {
+                let error = RoughError::new(ErrorKind::Name, format!("Identifier {} not found", name));
+                Err(vec![match env.borrow().similar_name(name) {
+                    Some(similar) => error.with_help(format!("did you mean {}?", similar)),
+                    None => error,
+                }])
+            },
+        },
+        Expression::Function(params, body, doc) => Ok(Object::Function(Rc::new(Function {
+            params: params.clone(),
+            body: (**body).clone(),
+            env: Rc::clone(env),
+            doc: doc.clone(),
+            is_generator: body.contains_yield(),
+            name: None,
+            position: exp.span.start,
+        }))),
+        Expression::Call(function, args) => {
+            let function = evaluate(function, env)?;
+            let args = args.iter()
+                .map(|arg| evaluate(arg, env))
+                .collect::<RoughResult<Vec<Object>>>()?;
+            call_function(function, args, Some(exp.span.start))
+        },
+        Expression::Prefix(op_def, right) => {
+            let right = evaluate(right, env)?;
+            eval_prefix(&op_def.identifier, right)
+        },
+        Expression::Infix(left, op_def, right) => {
+            let left = evaluate(left, env)?;
+            let right = evaluate(right, env)?;
+            eval_infix(left, &op_def.identifier, right)
+        },
+        Expression::Postfix(_, op_def) => new_error(ErrorKind::Runtime, format!("Postfix operator {} isn't supported yet", op_def.identifier)),
+        Expression::IndexMap(entries) => eval_index_map(entries, env),
+        Expression::Index(left, index) => {
+            let left = evaluate(left, env)?;
+            let index = evaluate(index, env)?;
+            lookup(&left, &index, false)
+        },
+        Expression::SafeIndex(left, index) => {
+            let left = evaluate(left, env)?;
+            if let Object::Nil = left {
+                return Ok(Object::Nil);
+            }
+            let index = evaluate(index, env)?;
+            lookup(&left, &index, true)
+        },
+        Expression::If(cond, cons, alt) => {
+            if evaluate(cond, env)?.is_truthy() {
+                evaluate(cons, env)
+            } else {
+                match alt {
+                    Some(alt) => evaluate(alt, env),
+                    None => Ok(Object::Nil),
+                }
+            }
+        },
+        Expression::Let(name, value, body, doc) => {
+            // Bind in a new environment so the value can refer to itself (for recursion).
+            let inner = Environment::new_enclosed(env);
+            let value = bind(evaluate(value, &inner)?, name, doc);
+            inner.borrow_mut().set(name.clone(), value);
+            evaluate(body, &inner)
+        },
+        Expression::Block(exps) => {
+            let mut result = Object::Nil;
+            for exp in exps {
+                result = evaluate(exp, env)?;
+            }
+            Ok(result)
+        },
+        Expression::Try(body, name, handler) => match evaluate(body, env) {
+            Ok(value) => Ok(value),
+            Err(errors) => {
+                let inner = Environment::new_enclosed(env);
+                inner.borrow_mut().set(name.clone(), caught(&errors));
+                evaluate(handler, &inner)
+            },
+        },
+        Expression::Comprehension(key, value, clauses) => {
+            let mut map = IndexMap::new();
+            eval_comprehension(key.as_deref(), value, clauses, env, &mut map)?;
+            Ok(Object::IndexMap(map))
+        },
+        // Functions with a yield in them are run by Generator instead.
+        Expression::Yield(_) | Expression::YieldFrom(_) => new_error(ErrorKind::Runtime, "yield can only be used inside a function".to_string()),
+        // parse_program gives errors instead of a tree with these in it.
+        Expression::Error => new_error(ErrorKind::Parse, "Code that failed to parse can't be run".to_string()),
+    }
+}
+
+/// For builtins calling functions they were given, so there's no call site.
+pub fn apply_function(function: Object, args: Vec<Object>) -> RoughResult<Object> {
+    call_function(function, args, None)
+}
+
+/// Calls the function, adding it to the stack trace of any errors that come out of it.
+fn call_function(function: Object, args: Vec<Object>, call_site: Option<usize>) -> RoughResult<Object> {
+    let name = match &function {
+        Object::Function(function) => function.callee(),
+        Object::Builtin(builtin) => Callee::Named(builtin.name.to_string()),
+        other => return new_error(ErrorKind::Type, format!("{} is not a function", other.type_name())),
+    };
+
+    deeper(|| apply(function, args, call_site))?.map_err(|mut errors| {
+        for error in &mut errors {
+            error.push_frame(TraceFrame { function: name.clone(), call_site });
+        }
+        errors
+    })
+}
+
+/// Runs f one level deeper, or gives an error if that would go past MAX_CALL_DEPTH.
+/// Iterators getting values from other iterators count as well as function calls,
+/// since a generator that yields from itself nests on the stack the same way.
+pub fn deeper<T>(f: impl FnOnce() -> T) -> RoughResult<T> {
+    let depth = CALL_DEPTH.with(Cell::get);
+    if depth >= MAX_CALL_DEPTH {
+        return new_error(ErrorKind::Runtime, format!("Calls and iterators went more than {} deep", MAX_CALL_DEPTH));
+    }
+
+    CALL_DEPTH.with(|current| current.set(depth + 1));
+    let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, f);
+    CALL_DEPTH.with(|current| current.set(depth));
+    Ok(result)
+}
+
+fn apply(function: Object, args: Vec<Object>, call_site: Option<usize>) -> RoughResult<Object> {
+    match function {
+        Object::Function(function) => {
+            if function.params.len() != args.len() {
+                let error = RoughError::new(ErrorKind::Arity, format!(
+                        "Function expected {} arguments but got {}",
+                        function.params.len(),
+                        args.len()
+                        ));
+                // Functions made by builtins, like compose, don't come from the source.
+                let defined = Span::new(function.position, function.body.span.end);
+                return Err(vec![if defined.end > defined.start {
+                    error.with_label(defined, "defined here")
+                } else {
+                    error
+                }]);
+            }
+
+            let inner = Environment::new_enclosed(&function.env);
+            for (param, arg) in function.params.iter().zip(args) {
+                inner.borrow_mut().set(param.clone(), arg);
+            }
+
+            if function.is_generator {
+                let frame = TraceFrame { function: function.callee(), call_site };
+                return Ok(Object::Iterator(Iter::generator(Generator::new(function.body.clone(), inner, frame))));
+            }
+
+            evaluate(&function.body, &inner)
+        },
+        Object::Builtin(builtin) => (builtin.func)(args),
+        other => new_error(ErrorKind::Type, format!("{} is not a function", other.type_name())),
+    }
+}
+
+/// Gives a function being bound its name and doc comment, unless it already has them.
+fn bind(value: Object, name: &str, doc: &Option<String>) -> Object {
+    match value {
+        Object::Function(function) if function.name.is_none() || (function.doc.is_none() && doc.is_some()) => {
+            Object::Function(Rc::new(Function {
+                params: function.params.clone(),
+                body: function.body.clone(),
+                env: Rc::clone(&function.env),
+                doc: function.doc.clone().or_else(|| doc.clone()),
+                is_generator: function.is_generator,
+                name: function.name.clone().or_else(|| Some(name.to_string())),
+                position: function.position,
+            }))
+        },
+        value => value,
+    }
+}
+
+fn eval_index_map(entries: &[(Option<Spanned<Expression>>, Spanned<Expression>)], env: &Env) -> RoughResult<Object> {
+    let mut map = IndexMap::new();
+
+    for (key, value) in entries {
+        let key = match key {
+            Some(key) => evaluate(key, env)?,
+            None => Object::Integer(map.len() as i64),
+        };
+        map.insert(key, evaluate(value, env)?);
+    }
+
+    Ok(Object::IndexMap(map))
+}
+
+/// Goes through the clauses in order, so each for loops over everything after it.
+/// Values go straight into the map as they're made, and the iterables are only
+/// gone through once without being collected first.
+fn eval_comprehension(
+    key: Option<&Spanned<Expression>>,
+    value: &Spanned<Expression>,
+    clauses: &[Clause],
+    env: &Env,
+    map: &mut IndexMap<Object, Object>
+    ) -> RoughResult<()> {
+    match clauses.split_first() {
+        None => {
+            let key = match key {
+                Some(key) => evaluate(key, env)?,
+                None => Object::Integer(map.len() as i64),
+            };
+            let value = evaluate(value, env)?;
+            map.insert(key, value);
+        },
+        Some((Clause::If(cond), rest)) => if evaluate(cond, env)?.is_truthy() {
+            eval_comprehension(key, value, rest, env, map)?;
+        },
+        Some((Clause::For(names, iterable), rest)) => {
+            for values in bindings(names, evaluate(iterable, env)?)? {
+                // A new environment each time so functions made in the value keep their own x.
+                let inner = Environment::new_enclosed(env);
+                for (name, value) in names.iter().zip(values?) {
+                    inner.borrow_mut().set(name.clone(), value);
+                }
+                eval_comprehension(key, value, rest, &inner, map)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// What the names in a for are set to each time around. Two names over an
+/// IndexMap get each key and value, and otherwise many names unpack each value.
+fn bindings(names: &[String], iterable: Object) -> RoughResult<Box<dyn Iterator<Item = RoughResult<Vec<Object>>>>> {
+    match (names.len(), iterable) {
+        (1, iterable) => Ok(Box::new(iterator::iterate(iterable)?.map(|value| Ok(vec![value?])))),
+        (2, Object::IndexMap(map)) => Ok(Box::new(map.into_iter().map(|(key, value)| Ok(vec![key, value])))),
+        (count, iterable) => Ok(Box::new(iterator::iterate(iterable)?.map(move |value| match value? {
+            Object::IndexMap(map) if map.len() == count => Ok(map.into_iter().map(|(_, value)| value).collect()),
+            other => new_error(ErrorKind::Value, format!("Can't unpack {} into {} names", other, count)),
+        }))),
+    }
+}
+
+/// The error value for catch. Only the first error is kept if there were many.
+fn caught(errors: &[RoughError]) -> Object {
+    match errors.first() {
+        Some(error) => error.to_value(),
+        None => RoughError::new(ErrorKind::Runtime, "Unknown error".to_string()).to_value(),
+    }
+}
+
+/// A safe lookup gives nil when the key is missing instead of an error.
+fn lookup(left: &Object, index: &Object, safe: bool) -> RoughResult<Object> {
+    match eval_index(left, index)? {
+        Some(value) => Ok(value),
+        None if safe => Ok(Object::Nil),
+        None => new_error(ErrorKind::Index, format!("Key {} not found", index)),
+    }
+}
+
+/// Gives None when the key is missing so the caller can decide if that's an error.
+/// Indexing with a range gives a slice of the values at those positions.
+fn eval_index(left: &Object, index: &Object) -> RoughResult<Option<Object>> {
+    match (left, index) {
+        (Object::IndexMap(map), Object::Range(positions)) => Ok(Some(Object::IndexMap(
+                    range::positions_within(positions, map.len())
+                    .filter_map(|position| map.get_index(position))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+                    ))),
+        (Object::IndexMap(map), index) => Ok(map.get(index).cloned()),
+        (Object::Range(range), Object::Range(positions)) => Ok(Some(Object::Range(range.slice(positions)))),
+        (Object::Range(range), Object::Integer(position)) if *position >= 0 => {
+            Ok(range.get(*position as u128).map(Object::Integer))
+        },
+        (Object::Range(_), Object::Integer(_)) => Ok(None),
+        (Object::Range(_), other) => new_error(ErrorKind::Type, format!("Ranges can only be indexed by Integers and Ranges, not {}", other.type_name())),
+        (Object::Error(error), Object::Symbol(field)) => Ok(match field.as_str() {
+            "kind" => Some(Object::Symbol(error.kind.clone())),
+            "message" => Some(Object::Str(error.message.clone())),
+            "payload" => Some(Object::IndexMap(error.payload.clone())),
+            _ => None,
+        }),
+        (Object::Str(string), Object::Range(positions)) => {
+            let chars: Vec<char> = string.chars().collect();
+            Ok(Some(Object::Str(range::positions_within(positions, chars.len())
+                        .map(|position| chars[position])
+                        .collect())))
+        },
+        (other, _) => new_error(ErrorKind::Type, format!("Can't index into {}", other.type_name())),
+    }
+}
+
+/// Runs the body of a generator function a bit at a time, stopping at each yield.
+///
+/// evaluate keeps its place in Rust's call stack, which can't be paused and picked
+/// up again later. So Generator keeps its own stack of frames instead, each saying
+/// what to do with the value of the expression being worked on. Parts of the body
+/// without a yield in them can't stop part way, so they're handed to evaluate.
+pub struct Generator {
+    state: State,
+    stack: Vec<Frame>,
+    /// The call that made the generator, for the stack traces of errors in its body.
+    call: TraceFrame,
+}
+
+enum State {
+    Eval(Spanned<Expression>, Env),
+    /// The value of the last expression, to be given to the frame on top of the stack.
+    Return(Object),
+    /// Going through the values of a yield from.
+    Delegate(ObjectIter),
+    Done,
+}
+
+/// Lists of expressions still to be evaluated are kept backwards so the next one can be popped off.
+enum Frame {
+    Interpolation { string: String, rest: Vec<Spanned<Expression>>, env: Env },
+    Callee { rest: Vec<Spanned<Expression>>, position: usize, env: Env },
+    Args { function: Object, args: Vec<Object>, rest: Vec<Spanned<Expression>>, position: usize, env: Env },
+    Prefix(String),
+    InfixLeft { op: String, right: Spanned<Expression>, env: Env },
+    InfixRight { left: Object, op: String },
+    IndexMapKey { map: IndexMap<Object, Object>, value: Spanned<Expression>, rest: Vec<(Option<Spanned<Expression>>, Spanned<Expression>)>, env: Env },
+    IndexMapValue { map: IndexMap<Object, Object>, key: Object, rest: Vec<(Option<Spanned<Expression>>, Spanned<Expression>)>, env: Env },
+    IndexLeft { index: Spanned<Expression>, safe: bool, env: Env },
+    IndexRight { left: Object, safe: bool },
+    If { cons: Spanned<Expression>, alt: Option<Spanned<Expression>>, env: Env },
+    Let { name: String, body: Spanned<Expression>, doc: Option<String>, env: Env },
+    Block { rest: Vec<Spanned<Expression>>, env: Env },
+    /// Errors from anything above this frame go to the handler.
+    Try { name: String, handler: Spanned<Expression>, env: Env },
+    Yield,
+    YieldFrom,
+}
+
+impl Generator {
+    pub fn new(body: Spanned<Expression>, env: Env, call: TraceFrame) -> Generator {
+        Generator {
+            state: State::Eval(body, env),
+            stack: vec![],
+            call,
+        }
+    }
+
+    /// Runs until the next value is yielded or the body finishes. An error also finishes it.
+    pub fn resume(&mut self) -> Option<RoughResult<Object>> {
+        loop {
+            let step = match mem::replace(&mut self.state, State::Done) {
+                State::Done => return None,
+                State::Eval(exp, env) => self.eval(exp, env),
+                State::Return(value) => match self.stack.pop() {
+                    Some(frame) => self.continue_with(frame, value),
+                    None => return None,
+                },
+                State::Delegate(mut iter) => match iter.next() {
+                    Some(Ok(value)) => {
+                        self.state = State::Delegate(iter);
+                        return Some(Ok(value));
+                    },
+                    Some(Err(errors)) => Err(errors),
+                    None => {
+                        self.state = State::Return(Object::Nil);
+                        Ok(None)
+                    },
+                },
+            };
+
+            match step {
+                Ok(Some(value)) => return Some(Ok(value)),
+                Ok(None) => (),
+                Err(mut errors) => if !self.catch(&errors) {
+                    self.stack.clear();
+                    self.state = State::Done;
+                    for error in &mut errors {
+                        error.push_frame(self.call.clone());
+                    }
+                    return Some(Err(errors));
+                },
+            }
+        }
+    }
+
+    /// Starts on an expression. Gives a value if it was yielded.
+    fn eval(&mut self, exp: Spanned<Expression>, env: Env) -> RoughResult<Option<Object>> {
+        if !exp.contains_yield() {
+            self.state = State::Return(evaluate(&exp, &env)?);
+            return Ok(None);
+        }
+
+        let Spanned { node, span } = exp;
+        match node {
+            Expression::Interpolation(mut rest) => {
+                rest.reverse();
+                self.next_part(String::new(), rest, env);
+            },
+            Expression::Call(function, mut rest) => {
+                rest.reverse();
+                self.push_eval(Frame::Callee { rest, position: span.start, env: Rc::clone(&env) }, *function, env);
+            },
+            Expression::Prefix(op_def, right) => self.push_eval(Frame::Prefix(op_def.identifier), *right, env),
+            Expression::Infix(left, op_def, right) => {
+                let frame = Frame::InfixLeft { op: op_def.identifier, right: *right, env: Rc::clone(&env) };
+                self.push_eval(frame, *left, env);
+            },
+            Expression::IndexMap(mut rest) => {
+                rest.reverse();
+                self.next_entry(IndexMap::new(), rest, env);
+            },
+            Expression::Index(left, index) => {
+                self.push_eval(Frame::IndexLeft { index: *index, safe: false, env: Rc::clone(&env) }, *left, env);
+            },
+            Expression::SafeIndex(left, index) => {
+                self.push_eval(Frame::IndexLeft { index: *index, safe: true, env: Rc::clone(&env) }, *left, env);
+            },
+            Expression::If(cond, cons, alt) => {
+                let frame = Frame::If { cons: *cons, alt: alt.map(|alt| *alt), env: Rc::clone(&env) };
+                self.push_eval(frame, *cond, env);
+            },
+            Expression::Let(name, value, body, doc) => {
+                let inner = Environment::new_enclosed(&env);
+                let frame = Frame::Let { name, body: *body, doc, env: Rc::clone(&inner) };
+                self.push_eval(frame, *value, inner);
+            },
+            Expression::Block(mut rest) => {
+                rest.reverse();
+                self.next_in_block(rest, env);
+            },
+            Expression::Try(body, name, handler) => {
+                self.push_eval(Frame::Try { name, handler: *handler, env: Rc::clone(&env) }, *body, env);
+            },
+            Expression::Yield(value) => self.push_eval(Frame::Yield, *value, env),
+            Expression::YieldFrom(value) => self.push_eval(Frame::YieldFrom, *value, env),
+            // The parser doesn't allow this, but an Expression could have been made some other way.
+            Expression::Comprehension(..) => return error_at(ErrorKind::Runtime, "yield can't be used inside a comprehension".to_string(), span),
+            other => self.state = State::Return(evaluate(&Spanned::new(other, span), &env)?),
+        }
+
+        Ok(None)
+    }
+
+    /// Carries on with a frame now that the value it was waiting for is ready.
+    fn continue_with(&mut self, frame: Frame, value: Object) -> RoughResult<Option<Object>> {
+        match frame {
+            Frame::Interpolation { mut string, rest, env } => {
+                string.push_str(&value.to_string());
+                self.next_part(string, rest, env);
+            },
+            Frame::Callee { rest, position, env } => self.next_arg(value, vec![], rest, position, env)?,
+            Frame::Args { function, mut args, rest, position, env } => {
+                args.push(value);
+                self.next_arg(function, args, rest, position, env)?;
+            },
+            Frame::Prefix(op) => self.state = State::Return(eval_prefix(&op, value)?),
+            Frame::InfixLeft { op, right, env } => self.push_eval(Frame::InfixRight { left: value, op }, right, env),
+            Frame::InfixRight { left, op } => self.state = State::Return(eval_infix(left, &op, value)?),
+            Frame::IndexMapKey { map, value: exp, rest, env } => {
+                let frame = Frame::IndexMapValue { map, key: value, rest, env: Rc::clone(&env) };
+                self.push_eval(frame, exp, env);
+            },
+            Frame::IndexMapValue { mut map, key, rest, env } => {
+                map.insert(key, value);
+                self.next_entry(map, rest, env);
+            },
+            Frame::IndexLeft { safe: true, .. } if value == Object::Nil => self.state = State::Return(Object::Nil),
+            Frame::IndexLeft { index, safe, env } => self.push_eval(Frame::IndexRight { left: value, safe }, index, env),
+            Frame::IndexRight { left, safe } => self.state = State::Return(lookup(&left, &value, safe)?),
+            Frame::If { cons, alt, env } => {
+                self.state = match (value.is_truthy(), alt) {
+                    (true, _) => State::Eval(cons, env),
+                    (false, Some(alt)) => State::Eval(alt, env),
+                    (false, None) => State::Return(Object::Nil),
+                };
+            },
+            Frame::Let { name, body, doc, env } => {
+                let value = bind(value, &name, &doc);
+                env.borrow_mut().set(name, value);
+                self.state = State::Eval(body, env);
+            },
+            Frame::Block { rest, env } => self.next_in_block(rest, env),
+            Frame::Try { .. } => self.state = State::Return(value),
+            Frame::Yield => {
+                self.state = State::Return(Object::Nil);
+                return Ok(Some(value));
+            },
+            Frame::YieldFrom => self.yield_from(value)?,
+        }
+
+        Ok(None)
+    }
+
+    /// Unwinds to the closest try and runs its handler, if there is one.
+    fn catch(&mut self, errors: &[RoughError]) -> bool {
+        let position = self.stack.iter().rposition(|frame| matches!(frame, Frame::Try { .. }));
+        let frame = match position {
+            Some(position) => self.stack.drain(position..).next(),
+            None => return false,
+        };
+
+        match frame {
+            Some(Frame::Try { name, handler, env }) => {
+                let inner = Environment::new_enclosed(&env);
+                inner.borrow_mut().set(name, caught(errors));
+                self.state = State::Eval(handler, inner);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn push_eval(&mut self, frame: Frame, exp: Spanned<Expression>, env: Env) {
+        self.stack.push(frame);
+        self.state = State::Eval(exp, env);
+    }
+
+    fn next_part(&mut self, string: String, mut rest: Vec<Spanned<Expression>>, env: Env) {
+        match rest.pop() {
+            Some(part) => self.push_eval(Frame::Interpolation { string, rest, env: Rc::clone(&env) }, part, env),
+            None => self.state = State::Return(Object::Str(string)),
+        }
+    }
+
+    fn next_arg(&mut self, function: Object, args: Vec<Object>, mut rest: Vec<Spanned<Expression>>, position: usize, env: Env) -> RoughResult<()> {
+        match rest.pop() {
+            Some(arg) => self.push_eval(Frame::Args { function, args, rest, position, env: Rc::clone(&env) }, arg, env),
+            None => self.state = State::Return(call_function(function, args, Some(position))?),
+        }
+        Ok(())
+    }
+
+    fn next_entry(&mut self, map: IndexMap<Object, Object>, mut rest: Vec<(Option<Spanned<Expression>>, Spanned<Expression>)>, env: Env) {
+        match rest.pop() {
+            Some((Some(key), value)) => self.push_eval(Frame::IndexMapKey { map, value, rest, env: Rc::clone(&env) }, key, env),
+            Some((None, value)) => {
+                let key = Object::Integer(map.len() as i64);
+                self.push_eval(Frame::IndexMapValue { map, key, rest, env: Rc::clone(&env) }, value, env);
+            },
+            None => self.state = State::Return(Object::IndexMap(map)),
+        }
+    }
+
+    /// The last expression doesn't need the block's frame any more, which
+    /// keeps a yield from at the end of a block in tail position.
+    fn next_in_block(&mut self, mut rest: Vec<Spanned<Expression>>, env: Env) {
+        match rest.pop() {
+            Some(exp) if rest.is_empty() => self.state = State::Eval(exp, env),
+            Some(exp) => self.push_eval(Frame::Block { rest, env: Rc::clone(&env) }, exp, env),
+            None => self.state = State::Return(Object::Nil),
+        }
+    }
+
+    /// When there's nothing left to do afterwards and the values come from a new
+    /// generator, this generator becomes that one rather than passing its values on.
+    /// That way a generator that ends by yielding from a call to itself doesn't
+    /// get slower or use more memory with every value.
+    fn yield_from(&mut self, value: Object) -> RoughResult<()> {
+        let value = match value {
+            Object::Iterator(iter) if self.stack.is_empty() => match iter.into_generator() {
+                Ok(generator) => {
+                    *self = generator;
+                    return Ok(());
+                },
+                Err(iter) => Object::Iterator(iter),
+            },
+            value => value,
+        };
+
+        self.state = State::Delegate(iterator::iterate(value)?);
+        Ok(())
+    }
+}
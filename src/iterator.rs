@@ -0,0 +1,171 @@
+use crate::object::Object;
+use crate::error::{ ErrorKind, RoughError, RoughResult, new_error, raise_error };
+use crate::evaluator::{ apply_function, deeper, Generator };
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{ BufRead, BufReader };
+use std::rc::Rc;
+
+/// Anything that gives values one at a time. Errors are given as items so they
+/// can stop whatever is using the iterator, like a function failing inside map.
+pub type ObjectIter = Box<dyn Iterator<Item = RoughResult<Object>>>;
+
+/// Where an iterator's values come from. Generators are kept separate so one
+/// generator can take over another (see Generator::resume).
+enum Source {
+    Host(ObjectIter),
+    Generator(Generator),
+}
+
+/// An iterator as a Rough value. Copies share their position, so taking values
+/// from one copy means the other won't see them.
+#[derive(Clone)]
+pub struct Iter {
+    inner: Rc<RefCell<Source>>,
+}
+
+impl Iter {
+    pub fn new(iter: ObjectIter) -> Iter {
+        Iter {
+            inner: Rc::new(RefCell::new(Source::Host(iter))),
+        }
+    }
+
+    pub fn generator(generator: Generator) -> Iter {
+        Iter {
+            inner: Rc::new(RefCell::new(Source::Generator(generator))),
+        }
+    }
+
+    /// For comparing and hashing, since iterators are only equal to themselves.
+    pub fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.inner) as *const ()
+    }
+
+    /// Gives back the generator behind this iterator if nothing else can see it.
+    pub fn into_generator(self) -> Result<Generator, Iter> {
+        match Rc::try_unwrap(self.inner) {
+            Ok(source) => match source.into_inner() {
+                Source::Generator(generator) => Ok(generator),
+                Source::Host(iter) => Err(Iter::new(iter)),
+            },
+            Err(inner) => Err(Iter { inner }),
+        }
+    }
+}
+
+impl Iterator for Iter {
+    type Item = RoughResult<Object>;
+
+    fn next(&mut self) -> Option<RoughResult<Object>> {
+        // Happens when an iterator is used by something it's giving values to,
+        // like a generator that yields from itself.
+        let mut source = match self.inner.try_borrow_mut() {
+            Ok(source) => source,
+            Err(_) => return Some(new_error(ErrorKind::Runtime, "An iterator can't be used while it's getting its next value".to_string())),
+        };
+
+        let next = deeper(|| match &mut *source {
+            Source::Host(iter) => iter.next(),
+            Source::Generator(generator) => generator.resume(),
+        });
+
+        next.unwrap_or_else(|errors| Some(Err(errors)))
+    }
+}
+
+impl fmt::Debug for Iter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Iter")
+    }
+}
+
+/// Gets the values of anything that can be iterated over: the values of an
+/// IndexMap, the characters of a string, the values in a range or an iterator.
+pub fn iterate(obj: Object) -> RoughResult<ObjectIter> {
+    match obj {
+        Object::IndexMap(map) => Ok(Box::new(map.into_iter().map(|(_, value)| Ok(value)))),
+        Object::Str(string) => {
+            let chars: Vec<char> = string.chars().collect();
+            Ok(Box::new(chars.into_iter().map(|c| Ok(Object::Str(c.to_string())))))
+        },
+        Object::Range(range) => Ok(Box::new(range.iter().map(|value| Ok(Object::Integer(value))))),
+        Object::Iterator(iter) => Ok(Box::new(iter)),
+        other => new_error(ErrorKind::Type, format!("{} can't be iterated over", other.type_name())),
+    }
+}
+
+/// The lines of a file, read as they're needed.
+pub fn lines(path: &str) -> RoughResult<ObjectIter> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => {
+            let payload = vec![(Object::Symbol("path".to_string()), Object::Str(path.to_string()))];
+            return raise_error(ErrorKind::Io, ErrorKind::Io.symbol(), format!("Couldn't open {}: {}", path, error), payload.into_iter().collect());
+        },
+    };
+
+    let path = path.to_string();
+    Ok(Box::new(BufReader::new(file).lines().map(move |line| match line {
+        Ok(line) => Ok(Object::Str(line)),
+        Err(error) => Err(vec![RoughError::new(ErrorKind::Io, format!("Couldn't read {}: {}", path, error))]),
+    })))
+}
+
+pub fn map(iter: ObjectIter, function: Object) -> ObjectIter {
+    Box::new(iter.map(move |value| apply_function(function.clone(), vec![value?])))
+}
+
+pub fn filter(iter: ObjectIter, predicate: Object) -> ObjectIter {
+    Box::new(iter.filter_map(move |value| {
+        let value = match value {
+            Ok(value) => value,
+            Err(errors) => return Some(Err(errors)),
+        };
+
+        match apply_function(predicate.clone(), vec![value.clone()]) {
+            Ok(keep) if keep.is_truthy() => Some(Ok(value)),
+            Ok(_) => None,
+            Err(errors) => Some(Err(errors)),
+        }
+    }))
+}
+
+/// Pairs are positional IndexMaps, like [a, b].
+pub fn pair(first: Object, second: Object) -> Object {
+    Object::IndexMap(vec![(Object::Integer(0), first), (Object::Integer(1), second)].into_iter().collect())
+}
+
+pub fn zip(left: ObjectIter, right: ObjectIter) -> ObjectIter {
+    Box::new(left.zip(right).map(|(left, right)| Ok(pair(left?, right?))))
+}
+
+pub fn enumerate(iter: ObjectIter) -> ObjectIter {
+    Box::new(iter.enumerate().map(|(i, value)| Ok(pair(Object::Integer(i as i64), value?))))
+}
+
+pub fn chain(iters: Vec<ObjectIter>) -> ObjectIter {
+    Box::new(iters.into_iter().flatten())
+}
+
+pub fn fold(iter: ObjectIter, init: Object, function: Object) -> RoughResult<Object> {
+    let mut acc = init;
+    for value in iter {
+        acc = apply_function(function.clone(), vec![acc, value?])?;
+    }
+    Ok(acc)
+}
+
+/// Puts the values in an IndexMap with positional keys.
+pub fn collect(iter: ObjectIter) -> RoughResult<Object> {
+    let mut values = vec![];
+    for value in iter {
+        values.push(value?);
+    }
+
+    Ok(Object::IndexMap(values.into_iter()
+            .enumerate()
+            .map(|(i, value)| (Object::Integer(i as i64), value))
+            .collect()))
+}
@@ -0,0 +1,98 @@
+use crate::token::{ Token, TokenType };
+use crate::error::{ ErrorKind, RoughResult, error_at };
+use crate::span::Span;
+
+/// The layout pass (AKA the offside rule).
+/// Turns changes in indentation into Indent and Dedent tokens, which the parser
+/// treats like brackets. That way a block can be written by indenting it instead
+/// of putting it in parentheses.
+///
+/// A line's indentation is its leading Tab tokens times the tab width plus its
+/// leading spaces. Blank lines and lines with only comments don't count, and
+/// neither does anything inside parentheses or square brackets, so expressions
+/// in them can still be split over lines however you like. Newlines inside
+/// brackets are dropped for the same reason, since the parser treats the rest
+/// as the ends of expressions.
+pub fn layout(tokens: Vec<Token>, tab_width: usize) -> RoughResult<Vec<Token>> {
+    let mut output = Vec::with_capacity(tokens.len());
+    // The first line's indentation is the base everything else is compared to.
+    let mut levels: Vec<usize> = vec![];
+    let mut bracket_depth: usize = 0;
+    let mut at_line_start = true;
+    let mut indentation = 0;
+    let mut last_position = 0;
+    // Where the last line break went in the output. Dedents go before it so the
+    // parser still sees the line break after the block ends.
+    let mut line_break = 0;
+
+    for token in tokens {
+        last_position = token.position();
+
+        match token.token_type {
+            TokenType::Newline if bracket_depth == 0 => {
+                at_line_start = true;
+                indentation = 0;
+                line_break = output.len();
+            },
+            TokenType::Newline => continue,
+            TokenType::Tab if at_line_start => indentation += tab_width,
+            TokenType::Space if at_line_start => indentation += 1,
+            TokenType::Tab | TokenType::Space => (),
+            TokenType::Comment(_) | TokenType::DocComment(_) => (),
+            // A line starting with |> carries on the pipeline from the line before,
+            // so its indentation doesn't matter.
+            TokenType::PipeForward if at_line_start => at_line_start = false,
+            _ if at_line_start => {
+                at_line_start = false;
+                indent_to(&mut levels, indentation, token.position(), &mut output, line_break)?;
+            },
+            _ => (),
+        }
+
+        match token.token_type {
+            TokenType::LParen | TokenType::LBracket | TokenType::SafeLBracket => bracket_depth += 1,
+            TokenType::RParen | TokenType::RBracket => bracket_depth = bracket_depth.saturating_sub(1),
+            _ => (),
+        }
+
+        output.push(token);
+    }
+
+    for _ in 1..levels.len() {
+        output.push(Token::new(TokenType::Dedent, last_position, last_position));
+    }
+
+    Ok(output)
+}
+
+fn indent_to(
+    levels: &mut Vec<usize>,
+    indentation: usize,
+    position: usize,
+    output: &mut Vec<Token>,
+    line_break: usize
+    ) -> RoughResult<()> {
+    let current = match levels.last() {
+        Some(current) => *current,
+        None => {
+            levels.push(indentation);
+            return Ok(());
+        },
+    };
+
+    if indentation > current {
+        levels.push(indentation);
+        output.push(Token::new(TokenType::Indent, position, position));
+    }
+
+    while levels.len() > 1 && indentation < *levels.last().unwrap_or(&0) {
+        levels.pop();
+        output.insert(line_break, Token::new(TokenType::Dedent, position, position));
+    }
+
+    if indentation < levels[0] || (indentation < current && levels.last() != Some(&indentation)) {
+        return error_at(ErrorKind::Lex, "Indentation doesn't line up with any outer block".to_string(), Span::new(position, position));
+    }
+
+    Ok(())
+}
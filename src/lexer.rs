@@ -1,5 +1,9 @@
-use crate::token::{ TokenType, Token };
-use crate::error::{ RoughError, RoughResult };
+use crate::token::{ TokenType, Token, StrPart };
+use crate::error::{ ErrorKind, RoughError, RoughResult, new_error, error_at, locate };
+use crate::span::Span;
+use num_bigint::BigInt;
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
 use std::str::CharIndices;
 use std::iter::Peekable;
 
@@ -7,51 +11,258 @@ use std::iter::Peekable;
 pub struct Lexer<'a> {
     source: &'a str,
     source_iter: Peekable<CharIndices<'a>>,
-    pub errors: Vec<RoughError>,
+    /// How many spaces in a row make a Tab token.
+    tab_width: usize,
+    /// Added to every position, for when the source is one of several files (see SourceMap).
+    offset: usize,
 }
 
-impl Lexer<'_> {
-    pub fn new(input: &str) -> Lexer {
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer::with_tab_width(input, DEFAULT_TAB_WIDTH)
+    }
+
+    pub fn with_tab_width(input: &'a str, tab_width: usize) -> Lexer<'a> {
         Lexer {
             source: input,
             source_iter: input.char_indices().peekable(),
-            errors: Vec::new(),
+            tab_width: tab_width.max(1),
+            offset: 0,
         }
     }
 
+    /// Makes positions count from offset instead of from 0.
+    pub fn starting_at(mut self, offset: usize) -> Lexer<'a> {
+        self.offset = offset;
+        self
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Where the next character is, or the end of the source if there isn't one.
+    fn position(&mut self) -> usize {
+        self.source_iter.peek().map(|(index, _)| *index).unwrap_or_else(|| self.source.len())
+    }
+
+    /// Identifiers are normalised to NFC so that names that look the same are the same.
     fn read_identifier(&mut self, first: char) -> String {
         let mut string = format!("{}", first);
 
-        while let Some((_, ch)) = self.source_iter.peek() {
-            if !is_letter(*ch) {
-                return string
-            };
-            string = format!("{}{}", string, ch.clone());
-            self.source_iter.next();
+        while let Some((_, ch)) = self.source_iter.next_if(|(_, ch)| is_identifier_char(*ch)) {
+            string.push(ch);
         }
 
-        string
+        string.nfc().collect()
     }
 
-    // TODO implement character escaping
-    fn read_string(&mut self) -> RoughResult<String> {
-        let mut string = "".to_string();
+    /// Strings with {...} in them are lexed into an Interpolated token,
+    /// otherwise they're a plain Str token.
+    fn read_string(&mut self) -> RoughResult<TokenType> {
+        let mut string = String::new();
+        let mut parts = vec![];
+        let mut errors = vec![];
 
         let mut closed = false;
 
-        while let Some((_, ch)) = self.source_iter.next() {
-            if ch == '"' {
-                closed = true;
-                break
-            };
-
-            string = format!("{}{}", string, ch);
+        // Keeps going after a bad escape so the rest of the string isn't lexed as code.
+        while let Some((index, ch)) = self.source_iter.next() {
+            match ch {
+                '"' => {
+                    closed = true;
+                    break
+                },
+                '\\' => match self.read_escape(index) {
+                    Ok(escaped) => string.push(escaped),
+                    Err(mut error) => errors.append(&mut error),
+                },
+                '{' => match self.read_interpolation(index) {
+                    Ok(tokens) => {
+                        parts.push(StrPart::Literal(std::mem::take(&mut string)));
+                        parts.push(StrPart::Code(tokens));
+                    },
+                    Err(mut error) => errors.append(&mut error),
+                },
+                other => string.push(other),
+            }
         }
 
         if !closed {
-            Err(vec!(RoughError::new("File ended before string closed".to_string())))
+            errors.push(RoughError::new(ErrorKind::Lex, "File ended before string closed".to_string()));
+        }
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else if parts.is_empty() {
+            Ok(TokenType::Str(string))
+        } else {
+            parts.push(StrPart::Literal(string));
+            parts.retain(|part| *part != StrPart::Literal(String::new()));
+            Ok(TokenType::Interpolated(parts))
+        }
+    }
+
+    /// Reads r"..." or r#"..."# where the number of #s can be anything, so the
+    /// string can contain "# too. Nothing is escaped or interpolated.
+    fn read_raw_string(&mut self, start: usize) -> RoughResult<String> {
+        let hashes = raw_string_hashes(&self.source[start + 1..]).unwrap_or(0);
+        let content_start = start + hashes + 2;
+        let closing = format!("\"{}", "#".repeat(hashes));
+
+        match self.source[content_start..].find(&closing) {
+            Some(length) => {
+                let end = content_start + length + closing.len();
+                while self.source_iter.next_if(|(index, _)| *index < end).is_some() {}
+                Ok(self.source[content_start..content_start + length].to_string())
+            },
+            None => {
+                self.source_iter.by_ref().for_each(drop);
+                self.error_from("File ended before the raw string was closed".to_string(), start, content_start)
+            },
+        }
+    }
+
+    /// Reads a \"\"\" heredoc. Like a raw string, nothing is escaped or interpolated.
+    /// A newline straight after the opening quotes and a last line of only
+    /// indentation are left out, then the indentation every line has in
+    /// common is removed, so the text can be indented along with the code.
+    fn read_heredoc(&mut self, start: usize) -> RoughResult<String> {
+        let content_start = start + 3;
+
+        let length = match self.source[content_start..].find("\"\"\"") {
+            Some(length) => length,
+            None => {
+                self.source_iter.by_ref().for_each(drop);
+                return self.error_from("File ended before the heredoc was closed".to_string(), start, content_start);
+            },
+        };
+
+        let end = content_start + length + 3;
+        while self.source_iter.next_if(|(index, _)| *index < end).is_some() {}
+
+        Ok(strip_indentation(&self.source[content_start..content_start + length]))
+    }
+
+    /// Lexes the code between the { at start and its matching }.
+    fn read_interpolation(&mut self, start: usize) -> RoughResult<Vec<Token>> {
+        let mut depth = 1;
+        let mut in_string = false;
+        let mut end = None;
+
+        // Only looks for the matching }, skipping over any strings inside.
+        while let Some((index, ch)) = self.source_iter.next() {
+            match ch {
+                '\\' if in_string => {
+                    self.source_iter.next();
+                },
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(index);
+                        break;
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        let end = match end {
+            Some(end) => end,
+            None => return self.error_from("File ended before the interpolation was closed".to_string(), start, start + 1),
+        };
+
+        let mut tokens = vec![];
+        let mut errors = vec![];
+
+        for result in self.sub_lexer(start + 1, end) {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(mut error) => errors.append(&mut error),
+            }
+        }
+
+        if !errors.is_empty() {
+            Err(errors)
+        } else if tokens.iter().all(|token| is_whitespace(&token.token_type)) {
+            self.error_from("Interpolation is empty".to_string(), start, end + 1)
         } else {
-            Ok(string.to_string())
+            Ok(tokens)
+        }
+    }
+
+    /// A lexer for just part of the source, so that positions still line up with the whole source.
+    fn sub_lexer(&self, start: usize, end: usize) -> Lexer<'a> {
+        let source = &self.source[..end];
+        let mut source_iter = source.char_indices().peekable();
+        while source_iter.next_if(|(index, _)| *index < start).is_some() {}
+
+        Lexer {
+            source,
+            source_iter,
+            tab_width: self.tab_width,
+            offset: self.offset,
+        }
+    }
+
+    /// Reads the escape after a backslash at start.
+    fn read_escape(&mut self, start: usize) -> RoughResult<char> {
+        let escaped = match self.source_iter.next() {
+            Some((_, '"')) => '"',
+            Some((_, '{')) => '{',
+            Some((_, '}')) => '}',
+            Some((_, '\\')) => '\\',
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '0')) => '\0',
+            Some((_, 'x')) => {
+                let digits: String = (0..2)
+                    .filter_map(|_| self.source_iter.next_if(|(_, ch)| ch.is_ascii_hexdigit()))
+                    .map(|(_, ch)| ch)
+                    .collect();
+
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(code) if digits.len() == 2 && code <= 0x7F => code as char,
+                    _ => return self.error_to_here("Escape should be \\x followed by two hex digits up to 7F".to_string(), start),
+                }
+            },
+            Some((_, 'u')) => self.read_unicode_escape(start)?,
+            Some((index, other)) => return Err(vec![
+                RoughError::new(ErrorKind::Lex, format!("Unknown escape \\{}", other))
+                    .or_span(self.span(start, index + other.len_utf8()))
+                    .with_note("the escapes are \\\" \\{ \\} \\\\ \\n \\t \\r \\0 \\xNN and \\u{NNNN}".to_string())
+            ]),
+            None => return self.error_to_here("File ended in the escape".to_string(), start),
+        };
+
+        Ok(escaped)
+    }
+
+    /// Reads the {...} part of a \u{...} escape.
+    fn read_unicode_escape(&mut self, start: usize) -> RoughResult<char> {
+        let error = "Escape should be \\u{...} with 1 to 6 hex digits of a valid character".to_string();
+
+        if self.source_iter.next_if(|(_, ch)| *ch == '{').is_none() {
+            return self.error_to_here(error, start);
+        }
+
+        let mut digits = String::new();
+        while let Some((_, ch)) = self.source_iter.next_if(|(_, ch)| ch.is_ascii_hexdigit()) {
+            digits.push(ch);
+        }
+
+        if self.source_iter.next_if(|(_, ch)| *ch == '}').is_none() || digits.is_empty() || digits.len() > 6 {
+            return self.error_to_here(error, start);
+        }
+
+        match u32::from_str_radix(&digits, 16).ok().and_then(std::char::from_u32) {
+            Some(ch) => Ok(ch),
+            None => self.error_to_here(error, start),
         }
     }
 
@@ -60,77 +271,226 @@ impl Lexer<'_> {
         let mut op = vec![first];
 
         while let Some((_, ch)) = self.source_iter.peek() {
-            if !is_op_char(ch) {
+            if !is_op_char(*ch) {
                 //return Ok(op.collect());
                 break;
             };
 
-            op.push(ch);
+            op.push(*ch);
             self.source_iter.next();
         }
 
         Ok(op.iter().collect())
     }
 
-    fn read_number(&mut self, first: char) -> f64 {
-        let mut number = format!("{}", first);
-
-        while let Some((_, ch)) = self.source_iter.peek() {
-            if !ch.is_ascii_digit() {
-                //return number.parse::<f64>().unwrap()
-                break;
+    /// Numbers are decimal with an optional fraction and exponent (e.g. 1_000.5e-3),
+    /// or are integers with a 0x, 0o or 0b prefix.
+    /// Underscores can be put between digits to separate them.
+    /// Only numbers with a fraction or exponent are Floats, the rest are Integers.
+    fn read_number(&mut self, start: usize, first: char) -> RoughResult<TokenType> {
+        if first == '0' {
+            let radix = match self.source_iter.peek() {
+                Some((_, 'x')) => Some(16),
+                Some((_, 'o')) => Some(8),
+                Some((_, 'b')) => Some(2),
+                _ => None,
             };
-            number = format!("{}{}", number, ch.clone());
+
+            if let Some(radix) = radix {
+                self.source_iter.next();
+                let digits = self.read_digits(start, None, radix)?;
+                self.check_number_end()?;
+                return parse_integer(&digits, radix);
+            }
+        }
+
+        let mut number = self.read_digits(start, Some(first), 10)?;
+        let mut is_float = false;
+
+        // Only a fraction if a digit follows, so things like 1..5 still work.
+        if self.peek_is('.') && self.peek_second().map(|ch| ch.is_ascii_digit()).unwrap_or(false) {
+            self.source_iter.next();
+            is_float = true;
+            number.push('.');
+            number.push_str(&self.read_digits(start, None, 10)?);
+        }
+
+        if self.peek_is('e') || self.peek_is('E') {
             self.source_iter.next();
+            is_float = true;
+            number.push('e');
+            if let Some((_, sign)) = self.source_iter.next_if(|(_, ch)| *ch == '+' || *ch == '-') {
+                number.push(sign);
+            }
+            number.push_str(&self.read_digits(start, None, 10)?);
+        }
+
+        self.check_number_end()?;
+
+        if !is_float {
+            return parse_integer(&number, 10);
         }
 
-        number.parse::<f64>().unwrap()
+        // Parsing doesn't fail for numbers too big for a Float, it gives inf instead.
+        match number.parse::<f64>() {
+            Ok(float) if float.is_finite() => Ok(TokenType::Float(float)),
+            Ok(_) => self.error_to_here("Float literal out of range".to_string(), start),
+            Err(_) => self.error_to_here("Invalid number literal".to_string(), start),
+        }
     }
 
-    fn read_comment(&mut self) -> RoughResult<String> {
-        let mut comment = "".to_string();
+    /// Reads at least one digit, leaving out underscores between digits.
+    fn read_digits(&mut self, start: usize, first: Option<char>, radix: u32) -> RoughResult<String> {
+        let mut digits: String = first.into_iter().collect();
+        let mut last_underscore = None;
 
-        while let Some((_, ch)) = self.source_iter.peek() {
+        while let Some((index, ch)) = self.source_iter.peek().cloned() {
+            if ch == '_' {
+                if digits.is_empty() || last_underscore.is_some() {
+                    return self.error_from("Misplaced _ in number literal".to_string(), index, index + 1);
+                }
+                last_underscore = Some(index);
+            } else if ch.is_digit(radix) {
+                digits.push(ch);
+                last_underscore = None;
+            } else {
+                break;
+            }
             self.source_iter.next();
-            if ch == '\n' {
-                break
-            } else if ch == '*' && self.source_iter.peek().map(|peek_ch| peek_ch.1 == '#').unwrap_or(false) {
-                self.source_iter.next();
-                break
-            };
+        }
 
-            comment = format!("{}{}", comment, ch);
+        if let Some(index) = last_underscore {
+            self.error_from("Number literal can't end with _".to_string(), index, index + 1)
+        } else if digits.is_empty() {
+            self.error_to_here("Number literal is missing digits".to_string(), start)
+        } else {
+            Ok(digits)
         }
+    }
+
+    /// A number running straight into a letter or digit (e.g. 12ab or 0b102) is malformed.
+    fn check_number_end(&mut self) -> RoughResult<()> {
+        match self.source_iter.peek().cloned() {
+            Some((index, ch)) if ch.is_alphanumeric() || ch == '_' => {
+                self.error_from(format!("Unexpected {} in number literal", ch), index, index + ch.len_utf8())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.offset + start, self.offset + end)
+    }
+
+    /// An error at the part of the source from start to end.
+    fn error_from<T>(&self, msg: String, start: usize, end: usize) -> RoughResult<T> {
+        error_at(ErrorKind::Lex, msg, self.span(start, end))
+    }
+
+    /// An error at the part of the source from start to the next character to be read.
+    fn error_to_here<T>(&mut self, msg: String, start: usize) -> RoughResult<T> {
+        let end = self.position();
+        self.error_from(msg, start, end)
+    }
 
-        Ok(comment.to_string())
+    fn peek_is(&mut self, expected: char) -> bool {
+        self.source_iter.peek().map(|(_, ch)| *ch == expected).unwrap_or(false)
     }
 
-    fn handle_error(&mut self, errors: Vec<RoughError>) -> Option<Token> {
-        self.errors.append(error.clone());
-        self.next()
+    /// Looks at the character after the next one.
+    fn peek_second(&mut self) -> Option<char> {
+        let (index, ch) = *self.source_iter.peek()?;
+        self.source[index + ch.len_utf8()..].chars().next()
+    }
+
+    /// A line comment goes up to the end of the line, but leaves the newline
+    /// to be lexed on its own.
+    fn read_comment(&mut self) -> String {
+        let mut comment = String::new();
+
+        while let Some((_, ch)) = self.source_iter.next_if(|(_, ch)| *ch != '\n' && *ch != '\r') {
+            comment.push(ch);
+        }
+
+        comment
+    }
+
+    /// Reads a #* ... *# comment after the #* at start.
+    /// Block comments can be nested, so each #* needs its own *#.
+    fn read_block_comment(&mut self, start: usize) -> RoughResult<String> {
+        let mut comment = String::new();
+        let mut depth = 1;
+
+        while let Some((_, ch)) = self.source_iter.next() {
+            if ch == '#' && self.peek_is('*') {
+                depth += 1;
+            } else if ch == '*' && self.peek_is('#') {
+                depth -= 1;
+                if depth == 0 {
+                    self.source_iter.next();
+                    return Ok(comment);
+                }
+            } else {
+                comment.push(ch);
+                continue;
+            }
+
+            // Keep nested delimiters as part of the comment.
+            comment.push(ch);
+            if let Some((_, second)) = self.source_iter.next() {
+                comment.push(second);
+            }
+        }
+
+        self.error_from("File ended before the block comment was closed".to_string(), start, start + 2)
     }
 }
 
 impl Iterator for Lexer<'_> {
-    type Item = Token;
+    type Item = RoughResult<Token>;
 
+    /// Errors are given the span of everything read for the token, unless
+    /// they already have a more exact one.
     fn next(&mut self) -> Option<Self::Item> {
-        let (start, cur_char) = match self.source_iter.next() {
-            Some(char_index) => char_index,
-            None => return None
-        };
+        let (start, cur_char) = self.source_iter.next()?;
+        let result = self.read_token(start, cur_char);
+        let span = Span::new(self.offset + start, self.offset + self.position());
+
+        Some(match result {
+            Ok(token_type) => Ok(Token::new(token_type, span.start, span.end)),
+            Err(errors) => Err(locate(errors, span)),
+        })
+    }
+}
 
+impl Lexer<'_> {
+    fn read_token(&mut self, start: usize, cur_char: char) -> RoughResult<TokenType> {
         let token_type = match cur_char {
             '(' => TokenType::LParen,
             ')' => TokenType::RParen,
             '[' => TokenType::LBracket,
             ',' => TokenType::Comma,
+            ';' => TokenType::Semicolon,
             ']' => TokenType::RBracket,
+            '|' if self.peek_is('>') => {
+                self.source_iter.next();
+                TokenType::PipeForward
+            },
             '|' => TokenType::Pipe,
-            '#' => match self.read_comment() {
-                Ok(comment) => TokenType::Comment(comment),
-                Err(error) => return self.handle_error(error),
-            }
+            '?' if self.source_iter.peek().map(|(_, ch)| *ch == '[').unwrap_or(false) => {
+                self.source_iter.next();
+                TokenType::SafeLBracket
+            },
+            '#' if self.peek_is('*') => {
+                self.source_iter.next();
+                TokenType::Comment(self.read_block_comment(start)?)
+            },
+            '#' if self.peek_is('#') => {
+                self.source_iter.next();
+                let doc = self.read_comment();
+                TokenType::DocComment(doc.strip_prefix(' ').map(str::to_string).unwrap_or(doc))
+            },
+            '#' => TokenType::Comment(self.read_comment()),
             '\n' => {
                 if let Some((_, '\r')) = self.source_iter.peek() {
                     self.source_iter.next();
@@ -150,53 +510,120 @@ impl Iterator for Lexer<'_> {
                 TokenType::Colon
             },
             '\t' => TokenType::Tab,
-            // Maybe make this configurable
-            ' ' => if self.source.len() >= start + 4 && &self.source[start..(start + 4)] == "    " {
-                // I feel like there's got to be some way to do something like this.
-                //self.source_iter.take(3).for_each(drop);
-                self.source_iter.next();
-                self.source_iter.next();
-                self.source_iter.next();
+            ' ' => if self.source[start..].starts_with(&" ".repeat(self.tab_width)) {
+                for _ in 1..self.tab_width {
+                    self.source_iter.next();
+                }
                 TokenType::Tab
             } else {
                 TokenType::Space
             },
-            // TODO escaping double quotes
-            '"' => match self.read_string() {
-                Ok(string) => TokenType::Str(string),
-                Err(error) => return self.handle_error(error),
-            },
+            '"' if self.source[start..].starts_with("\"\"\"") => TokenType::Str(self.read_heredoc(start)?),
+            '"' => self.read_string()?,
+            'r' if raw_string_hashes(&self.source[start + 1..]).is_some() => TokenType::Str(self.read_raw_string(start)?),
 
-            other if is_op_char(other) => match self.read_operator(other) {
-                Ok(op) => TokenType::Operator(op),
-                Err(error) => return self.handle_error(error),
+            '\'' => match self.source_iter.next() {
+                Some((_, first)) if is_letter(first) => TokenType::Symbol(self.read_identifier(first)),
+                _ => return new_error(ErrorKind::Lex, "Expected a name after ' for a symbol".to_string()),
             },
 
-            other if other.is_ascii_digit() => TokenType::Number(self.read_number(other)),
+            other if is_op_char(other) => TokenType::Operator(self.read_operator(other)?),
+
+            other if other.is_ascii_digit() => match self.read_number(start, other) {
+                Ok(number) => number,
+                Err(error) => {
+                    // Skip the rest of the malformed literal so it isn't lexed as something else.
+                    while self.source_iter.next_if(|(_, ch)| ch.is_alphanumeric() || *ch == '_').is_some() {}
+                    return Err(error)
+                },
+            },
                 
             other if is_letter(other) => lookup_ident(self.read_identifier(other)),
 
-            other => return self.handle_error(
-                RoughError::new(
-                    vec![
-                    format!(
-                        "Lexer error with character {}",
-                        other
-                        )
-                    ]
-                    )
-                )
+            other => return new_error(ErrorKind::Lex, unexpected_char_message(other)),
         };
 
-        Some(Token::new(token_type, start))
+        Ok(token_type)
+    }
+}
+
+/// If the text is the rest of a raw string's opening (after the r), gives the number of #s.
+fn raw_string_hashes(text: &str) -> Option<usize> {
+    let hashes = text.chars().take_while(|ch| *ch == '#').count();
+
+    if text[hashes..].starts_with('"') {
+        Some(hashes)
+    } else {
+        None
+    }
+}
+
+fn is_indentation(ch: char) -> bool {
+    ch == ' ' || ch == '\t'
+}
+
+fn strip_indentation(text: &str) -> String {
+    let text = text.strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+        .unwrap_or(text);
+
+    let mut lines: Vec<&str> = text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+
+    // The closing quotes' indentation still counts towards what's in common.
+    let closing_indent = match lines.last() {
+        Some(last) if lines.len() > 1 && last.chars().all(is_indentation) => lines.pop(),
+        _ => None,
+    };
+
+    let common = lines.iter()
+        .filter(|line| !line.chars().all(is_indentation))
+        .chain(closing_indent.iter())
+        .map(|line| &line[..line.len() - line.trim_start_matches(is_indentation).len()])
+        .fold(None, |common: Option<&str>, indent| match common {
+            None => Some(indent),
+            Some(common) => {
+                let same = common.char_indices()
+                    .zip(indent.chars())
+                    .take_while(|((_, l), r)| l == r)
+                    .count();
+                Some(&common[..same])
+            },
+        })
+        .unwrap_or("");
+
+    lines.iter()
+        .map(|line| line.strip_prefix(common).unwrap_or_else(|| line.trim_start_matches(is_indentation)))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn is_whitespace(token_type: &TokenType) -> bool {
+    matches!(token_type, TokenType::Space | TokenType::Tab | TokenType::Newline)
+}
+
+fn parse_integer(digits: &str, radix: u32) -> RoughResult<TokenType> {
+    match BigInt::parse_bytes(digits.as_bytes(), radix) {
+        Some(integer) => Ok(TokenType::Integer(integer)),
+        None => new_error(ErrorKind::Lex, "Invalid number literal".to_string()),
     }
 }
 
 fn check_keyword(word: &str) -> Option<TokenType> {
     match word {
         "if" => Some(TokenType::If),
+        "then" => Some(TokenType::Then),
         "else" => Some(TokenType::Else),
+        "cond" => Some(TokenType::Cond),
         "in" => Some(TokenType::In),
+        "for" => Some(TokenType::For),
+        "yield" => Some(TokenType::Yield),
+        "try" => Some(TokenType::Try),
+        "catch" => Some(TokenType::Catch),
+        "nil" => Some(TokenType::Nil),
+        "true" => Some(TokenType::True),
+        "false" => Some(TokenType::False),
         _ => None
     }
 }
@@ -208,15 +635,66 @@ fn lookup_ident(ident: String) -> TokenType {
     }
 }
 
-/// Want to expand this later, but wary of things like null characters.
+/// Whether an identifier can start with the character.
+/// Uses Unicode's XID_Start, so letters from any script work, but things like
+/// null characters and invisible formatting characters don't.
 fn is_letter(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+    ch == '_' || UnicodeXID::is_xid_start(ch)
+}
+
+/// Whether the character can come after the first one in an identifier.
+/// XID_Continue also lets through digits and combining marks.
+/// Suffixes like ? and ! aren't allowed since they would clash with
+/// operators, e.g. m?[k] and x!=y.
+fn is_identifier_char(ch: char) -> bool {
+    ch == '_' || UnicodeXID::is_xid_continue(ch)
 }
 
 fn is_op_char(ch: char) -> bool {
-    OPERATOR_CHARACTERS.iter()
-        .position(|op_ch| op_ch == ch)
-        .is_some()
+    OPERATOR_CHARACTERS.contains(&ch) || is_unicode_op_char(ch)
 }
+
+/// Non-ASCII operator characters are the math symbols and arrows from these
+/// Unicode blocks, leaving out the brackets in them.
+fn is_unicode_op_char(ch: char) -> bool {
+    let in_blocks = matches!(ch,
+        '¬' | '±' | '×' | '÷'
+        | '\u{2190}'..='\u{21FF}' // Arrows
+        | '\u{2200}'..='\u{22FF}' // Mathematical Operators
+        | '\u{27C0}'..='\u{27EF}' // Miscellaneous Mathematical Symbols-A
+        | '\u{27F0}'..='\u{27FF}' // Supplemental Arrows-A
+        | '\u{2900}'..='\u{297F}' // Supplemental Arrows-B
+        | '\u{2980}'..='\u{29FF}' // Miscellaneous Mathematical Symbols-B
+        | '\u{2A00}'..='\u{2AFF}' // Supplemental Mathematical Operators
+        );
+
+    let is_bracket = matches!(ch,
+        '\u{27C5}'..='\u{27C6}'
+        | '\u{27E6}'..='\u{27EF}'
+        | '\u{2983}'..='\u{2998}'
+        | '\u{29D8}'..='\u{29DB}'
+        | '\u{29FC}'..='\u{29FD}'
+        );
+
+    in_blocks && !is_bracket
+}
+
+/// Tries to say why a character isn't allowed, since a lot of them are easy to
+/// paste in by accident and look like something that is allowed.
+fn unexpected_char_message(ch: char) -> String {
+    let hint = match ch {
+        '\u{201C}' | '\u{201D}' | '\u{201E}' => " Strings use plain double quotes (\").",
+        '\u{2018}' | '\u{2019}' => " Symbols use a plain single quote (').",
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{3000}' => " It's a non-ASCII space.",
+        '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => " It's invisible, so it was probably pasted in by accident.",
+        '\u{2013}' | '\u{2014}' => " Did you mean -?",
+        ch if ch.is_control() => " It's a control character.",
+        ch if ch.is_numeric() => " Only ASCII digits can be used in numbers.",
+        _ => "",
+    };
+
+    format!("Unexpected character {} (U+{:04X}).{}", ch.escape_debug(), ch as u32, hint)
+}
+
 /// Want to expand this too, but need to start somewhere.
 const OPERATOR_CHARACTERS: [char; 19] = ['!', '$', '%', '&', '*', '+', '.', '/', '<', '=', '>', '?', '@', '\\', '^', '-', '~', '{', '}'];
@@ -1,5 +1,9 @@
 pub mod lexer;
+pub mod layout;
 pub mod token;
+pub mod span;
+pub mod source_map;
+pub mod diagnostic;
 pub mod repl;
 pub mod ast;
 pub mod parser;
@@ -9,3 +13,6 @@ pub mod environment;
 pub mod builtin;
 pub mod error;
 pub mod operator;
+pub mod number;
+pub mod range;
+pub mod iterator;
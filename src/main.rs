@@ -1,3 +1,40 @@
+use rough::repl;
+use rough::environment::Environment;
+use rough::source_map::SourceMap;
+use rough::diagnostic::Renderer;
+use std::env;
+use std::fs;
+use std::io::{ self, IsTerminal };
+use std::process;
+
+/// Runs the script given as an argument, or starts the REPL if there isn't one.
 fn main() {
-    println!("Hello, world!");
+    match env::args().nth(1) {
+        Some(path) => {
+            let source = fs::read_to_string(&path).unwrap_or_else(|error| {
+                eprintln!("Couldn't read {}: {}", path, error);
+                process::exit(1);
+            });
+
+            let mut sources = SourceMap::new();
+            if let Err(errors) = repl::run_file(&mut sources, &path, &source, &Environment::global()) {
+                let colour = use_colour(io::stderr().is_terminal());
+                eprint!("{}", Renderer::new(&sources, colour).render_all(&errors));
+                process::exit(1);
+            }
+        },
+        None => {
+            let stdin = io::stdin();
+            let colour = use_colour(io::stdout().is_terminal());
+            if let Err(error) = repl::start(stdin.lock(), io::stdout(), colour) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+    }
+}
+
+/// Colour is only used on a terminal, and never when NO_COLOR is set.
+fn use_colour(terminal: bool) -> bool {
+    terminal && env::var_os("NO_COLOR").is_none()
 }
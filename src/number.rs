@@ -0,0 +1,317 @@
+use crate::object::Object;
+use crate::error::{ ErrorKind, RoughResult, new_error };
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{ FromPrimitive, ToPrimitive, Zero };
+use std::cmp::Ordering;
+use std::hash::{ Hash, Hasher };
+
+// The numeric tower, from narrowest to widest:
+// Integer (i64) -> BigInt -> Rational -> Float.
+// Arithmetic widens both sides to the wider of the two, and exact results are
+// narrowed back down again, so a BigInt that fits in an i64 is always an Integer
+// and a Rational with a denominator of 1 is always an Integer or BigInt.
+
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum Level {
+    Integer,
+    BigInt,
+    Rational,
+    Float,
+}
+
+fn level(obj: &Object) -> Option<Level> {
+    match obj {
+        Object::Integer(_) => Some(Level::Integer),
+        Object::BigInt(_) => Some(Level::BigInt),
+        Object::Rational(_) => Some(Level::Rational),
+        Object::Float(_) => Some(Level::Float),
+        _ => None,
+    }
+}
+
+pub fn is_number(obj: &Object) -> bool {
+    level(obj).is_some()
+}
+
+pub fn from_big(big: BigInt) -> Object {
+    match big.to_i64() {
+        Some(small) => Object::Integer(small),
+        None => Object::BigInt(big),
+    }
+}
+
+pub fn from_rational(ratio: BigRational) -> Object {
+    if ratio.is_integer() {
+        from_big(ratio.to_integer())
+    } else {
+        Object::Rational(ratio)
+    }
+}
+
+fn to_big(obj: &Object) -> BigInt {
+    match obj {
+        Object::Integer(small) => BigInt::from(*small),
+        Object::BigInt(big) => big.clone(),
+        _ => unreachable!("to_big is only used on integers"),
+    }
+}
+
+fn to_rational(obj: &Object) -> BigRational {
+    match obj {
+        Object::Rational(ratio) => ratio.clone(),
+        other => BigRational::from_integer(to_big(other)),
+    }
+}
+
+pub fn to_float(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Integer(small) => Some(*small as f64),
+        Object::BigInt(big) => big.to_f64(),
+        Object::Rational(ratio) => ratio.to_f64(),
+        Object::Float(float) => Some(*float),
+        _ => None,
+    }
+}
+
+/// Unlike IEEE-754, NaN is equal to itself and comes after every other number,
+/// and -0 is equal to 0. This keeps equality, ordering and hashing consistent.
+fn compare_floats(left: f64, right: f64) -> Ordering {
+    match (left.is_nan(), right.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Compares exactly, so large integers aren't rounded to the nearest float.
+fn compare_float_exact(float: f64, exact: &BigRational) -> Ordering {
+    match BigRational::from_float(float) {
+        Some(ratio) => ratio.cmp(exact),
+        // NaN or infinite.
+        None if float == f64::NEG_INFINITY => Ordering::Less,
+        None => Ordering::Greater,
+    }
+}
+
+/// Numbers are compared by value whatever their type, so 1 == 1.0.
+pub fn compare(left: &Object, right: &Object) -> Ordering {
+    match (left, right) {
+        (Object::Integer(l), Object::Integer(r)) => l.cmp(r),
+        (Object::Float(l), Object::Float(r)) => compare_floats(*l, *r),
+        (Object::Float(l), r) => compare_float_exact(*l, &to_rational(r)),
+        (l, Object::Float(r)) => compare_float_exact(*r, &to_rational(l)).reverse(),
+        (l, r) => to_rational(l).cmp(&to_rational(r)),
+    }
+}
+
+/// Equal numbers of different types need to hash the same, so each is hashed
+/// as the narrowest exact type that can hold its value.
+#[derive(Hash)]
+enum Canonical {
+    Integer(i64),
+    BigInt(BigInt),
+    Rational(BigInt, BigInt),
+    Infinity(bool),
+    NaN,
+}
+
+pub fn hash<H: Hasher>(obj: &Object, state: &mut H) {
+    let canonical = match obj {
+        Object::Integer(small) => Canonical::Integer(*small),
+        Object::BigInt(big) => Canonical::BigInt(big.clone()),
+        Object::Rational(ratio) => Canonical::Rational(ratio.numer().clone(), ratio.denom().clone()),
+        Object::Float(float) if float.is_nan() => Canonical::NaN,
+        Object::Float(float) if float.is_infinite() => Canonical::Infinity(*float > 0.0),
+        Object::Float(float) => match from_rational(BigRational::from_float(*float).unwrap_or_default()) {
+            Object::Integer(small) => Canonical::Integer(small),
+            Object::BigInt(big) => Canonical::BigInt(big),
+            Object::Rational(ratio) => Canonical::Rational(ratio.numer().clone(), ratio.denom().clone()),
+            _ => Canonical::NaN,
+        },
+        _ => return,
+    };
+
+    canonical.hash(state);
+}
+
+pub fn negate(obj: Object) -> RoughResult<Object> {
+    match obj {
+        Object::Integer(small) => Ok(small.checked_neg()
+            .map(Object::Integer)
+            .unwrap_or_else(|| from_big(-BigInt::from(small)))),
+        Object::BigInt(big) => Ok(from_big(-big)),
+        Object::Rational(ratio) => Ok(from_rational(-ratio)),
+        Object::Float(float) => Ok(Object::Float(-float)),
+        other => new_error(ErrorKind::Type, format!("Prefix operator - can't be used on {}", other.type_name())),
+    }
+}
+
+/// Handles +, -, *, /, // (floor division) and % (which floors too, so it has the sign of the right side).
+/// Dividing integers gives an integer when it's exact, and a Float otherwise.
+/// Rationals only come from the rational builtin, but stay exact once made.
+pub fn arithmetic(left: &Object, op: &str, right: &Object) -> RoughResult<Object> {
+    let level = match (level(left), level(right)) {
+        (Some(l), Some(r)) => if l > r { l } else { r },
+        _ => return new_error(ErrorKind::Type, format!(
+                "Operator {} can't be used on {} and {}",
+                op,
+                left.type_name(),
+                right.type_name()
+                )),
+    };
+
+    if level == Level::Float {
+        return float_arithmetic(to_float(left).unwrap_or(f64::NAN), op, to_float(right).unwrap_or(f64::NAN));
+    }
+
+    if op != "+" && op != "-" && op != "*" && is_zero(right) {
+        return new_error(ErrorKind::Value, "Division by zero".to_string());
+    }
+
+    if let (Object::Integer(l), Object::Integer(r)) = (left, right) {
+        if let Some(result) = small_arithmetic(*l, op, *r) {
+            return result;
+        }
+    }
+
+    if level == Level::Rational {
+        rational_arithmetic(to_rational(left), op, to_rational(right))
+    } else {
+        big_arithmetic(to_big(left), op, to_big(right))
+    }
+}
+
+fn is_zero(obj: &Object) -> bool {
+    match obj {
+        Object::Integer(small) => *small == 0,
+        Object::BigInt(big) => big.is_zero(),
+        Object::Rational(ratio) => ratio.is_zero(),
+        _ => false,
+    }
+}
+
+/// Gives None on overflow so the caller can retry with BigInts.
+fn small_arithmetic(left: i64, op: &str, right: i64) -> Option<RoughResult<Object>> {
+    let result = match op {
+        "+" => left.checked_add(right)?,
+        "-" => left.checked_sub(right)?,
+        "*" => left.checked_mul(right)?,
+        "/" => if left.checked_rem(right)? == 0 {
+            left.checked_div(right)?
+        } else {
+            return Some(Ok(Object::Float(left as f64 / right as f64)));
+        },
+        "//" => {
+            // Catches i64::MIN // -1 overflowing.
+            left.checked_div(right)?;
+            Integer::div_floor(&left, &right)
+        },
+        "%" => {
+            // Catches i64::MIN % -1 overflowing.
+            left.checked_rem(right)?;
+            Integer::mod_floor(&left, &right)
+        },
+        _ => return Some(unknown_operator(op)),
+    };
+
+    Some(Ok(Object::Integer(result)))
+}
+
+fn big_arithmetic(left: BigInt, op: &str, right: BigInt) -> RoughResult<Object> {
+    let result = match op {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => if (&left % &right).is_zero() {
+            left / right
+        } else {
+            // Dividing as a fraction first means the quotient only has to fit in a Float,
+            // not both sides of it.
+            return match BigRational::new(left, right).to_f64() {
+                Some(quotient) if quotient.is_finite() => Ok(Object::Float(quotient)),
+                _ => new_error(ErrorKind::Value, "The result of / is too big to be a Float. rational can give it exactly".to_string()),
+            };
+        },
+        "//" => left.div_floor(&right),
+        "%" => left.mod_floor(&right),
+        _ => return unknown_operator(op),
+    };
+
+    Ok(from_big(result))
+}
+
+fn rational_arithmetic(left: BigRational, op: &str, right: BigRational) -> RoughResult<Object> {
+    let result = match op {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        "//" => (&left / &right).floor(),
+        "%" => &left - &right * (&left / &right).floor(),
+        _ => return unknown_operator(op),
+    };
+
+    Ok(from_rational(result))
+}
+
+fn float_arithmetic(left: f64, op: &str, right: f64) -> RoughResult<Object> {
+    let result = match op {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        "//" => (left / right).floor(),
+        "%" => {
+            // Moving the remainder over by right gives it the same sign as right, like the
+            // integer one, without the rounding errors of working it out from left // right.
+            let remainder = left % right;
+            if remainder == 0.0 {
+                0.0_f64.copysign(right)
+            } else if (remainder < 0.0) != (right < 0.0) {
+                remainder + right
+            } else {
+                remainder
+            }
+        },
+        _ => return unknown_operator(op),
+    };
+
+    Ok(Object::Float(result))
+}
+
+fn unknown_operator(op: &str) -> RoughResult<Object> {
+    new_error(ErrorKind::Type, format!("Operator {} isn't defined for numbers", op))
+}
+
+/// Converts a number to an exact Rational, or an Integer if it's whole.
+pub fn make_rational(numer: &Object, denom: &Object) -> RoughResult<Object> {
+    for obj in [numer, denom].iter() {
+        match level(obj) {
+            Some(Level::Float) => return new_error(ErrorKind::Value, "rational can't be made from a Float".to_string()),
+            None => return new_error(ErrorKind::Type, format!("rational can't be made from {}", obj.type_name())),
+            _ => (),
+        }
+    }
+
+    if is_zero(denom) {
+        return new_error(ErrorKind::Value, "Division by zero".to_string());
+    }
+
+    Ok(from_rational(to_rational(numer) / to_rational(denom)))
+}
+
+/// Truncates towards zero.
+pub fn to_integer(obj: &Object) -> RoughResult<Object> {
+    match obj {
+        Object::Integer(_) | Object::BigInt(_) => Ok(obj.clone()),
+        Object::Rational(ratio) => Ok(from_big(ratio.trunc().to_integer())),
+        Object::Float(float) => match BigInt::from_f64(float.trunc()) {
+            Some(big) => Ok(from_big(big)),
+            None => new_error(ErrorKind::Value, format!("{} can't be made into an Integer", float)),
+        },
+        other => new_error(ErrorKind::Value, format!("{} can't be made into an Integer", other.type_name())),
+    }
+}
@@ -1,16 +1,225 @@
+use crate::ast::Expression;
+use crate::span::Spanned;
+use crate::environment::Env;
+use crate::error::{ RoughResult, Callee };
+use crate::number;
+use crate::range::Range;
+use crate::iterator::Iter;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{ Hash, Hasher };
+use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Object {
-    Number(f64),
+    /// The value of "nothing", e.g. an if without an else.
+    Nil,
+    Bool(bool),
+    Integer(i64),
+    /// Only used for integers too big for an i64.
+    BigInt(BigInt),
+    /// Only used when the denominator isn't 1.
+    Rational(BigRational),
+    Float(f64),
     Str(String),
+    Symbol(String),
+    IndexMap(IndexMap<Object, Object>),
+    Range(Range),
+    Iterator(Iter),
+    Error(Rc<ErrorValue>),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+}
+
+impl Object {
+    /// Only false and nil are falsy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Nil | Object::Bool(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Nil => "Nil",
+            Object::Bool(_) => "Bool",
+            Object::Integer(_) | Object::BigInt(_) => "Integer",
+            Object::Rational(_) => "Rational",
+            Object::Float(_) => "Float",
+            Object::Str(_) => "String",
+            Object::Symbol(_) => "Symbol",
+            Object::IndexMap(_) => "IndexMap",
+            Object::Range(_) => "Range",
+            Object::Iterator(_) => "Iterator",
+            Object::Error(_) => "Error",
+            Object::Function(_) => "Function",
+            Object::Builtin(_) => "Function",
+        }
+    }
+
+    /// Where each type sits relative to the others when ordering.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Object::Nil => 0,
+            Object::Bool(_) => 1,
+            Object::Integer(_) | Object::BigInt(_) | Object::Rational(_) | Object::Float(_) => 2,
+            Object::Str(_) => 3,
+            Object::Symbol(_) => 4,
+            Object::IndexMap(_) => 5,
+            Object::Range(_) => 6,
+            Object::Error(_) => 7,
+            Object::Iterator(_) => 8,
+            Object::Function(_) => 9,
+            Object::Builtin(_) => 10,
+        }
+    }
+}
+
+/// The one definition of equality used by the evaluator, sorting and IndexMap keys.
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Object {}
+
+impl PartialOrd for Object {
+    fn partial_cmp(&self, other: &Object) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Values of different types are ordered by type: nil, bools, numbers,
+/// strings, symbols, IndexMaps, ranges, errors, iterators then functions.
+/// Numbers of any type are compared by value (see number::compare).
+/// IndexMaps are compared entry by entry in order, so the order of keys matters.
+impl Ord for Object {
+    fn cmp(&self, other: &Object) -> Ordering {
+        match (self, other) {
+            (Object::Nil, Object::Nil) => Ordering::Equal,
+            (Object::Bool(l), Object::Bool(r)) => l.cmp(r),
+            (l, r) if number::is_number(l) && number::is_number(r) => number::compare(l, r),
+            (Object::Str(l), Object::Str(r)) => l.cmp(r),
+            (Object::Symbol(l), Object::Symbol(r)) => l.cmp(r),
+            (Object::IndexMap(l), Object::IndexMap(r)) => l.iter().cmp(r.iter()),
+            (Object::Range(l), Object::Range(r)) => l.cmp(r),
+            (Object::Error(l), Object::Error(r)) => l.kind.cmp(&r.kind)
+                .then_with(|| l.message.cmp(&r.message))
+                .then_with(|| l.payload.iter().cmp(r.payload.iter())),
+            // Iterators and functions are only equal to themselves.
+            (Object::Iterator(l), Object::Iterator(r)) => l.as_ptr().cmp(&r.as_ptr()),
+            (Object::Function(l), Object::Function(r)) => Rc::as_ptr(l).cmp(&Rc::as_ptr(r)),
+            (Object::Builtin(l), Object::Builtin(r)) => l.name.cmp(r.name),
+            (l, r) => l.type_rank().cmp(&r.type_rank()),
+        }
+    }
+}
+
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_rank().hash(state);
+        match self {
+            Object::Nil => (),
+            Object::Bool(boolean) => boolean.hash(state),
+            Object::Integer(_) | Object::BigInt(_) | Object::Rational(_) | Object::Float(_) => {
+                number::hash(self, state)
+            },
+            Object::Str(string) => string.hash(state),
+            Object::Symbol(name) => name.hash(state),
+            Object::IndexMap(map) => {
+                map.len().hash(state);
+                for entry in map {
+                    entry.hash(state);
+                }
+            },
+            Object::Range(range) => range.hash(state),
+            Object::Iterator(iter) => iter.as_ptr().hash(state),
+            Object::Error(error) => {
+                error.kind.hash(state);
+                error.message.hash(state);
+                for entry in &error.payload {
+                    entry.hash(state);
+                }
+            },
+            Object::Function(function) => Rc::as_ptr(function).hash(state),
+            Object::Builtin(builtin) => builtin.name.hash(state),
+        }
+    }
 }
 
 impl fmt::Display for Object {
     fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
-        match &*self {
-            Object::Number(number) => write!(f, "{}", number),
+        match self {
+            Object::Nil => write!(f, "nil"),
+            Object::Bool(boolean) => write!(f, "{}", boolean),
+            Object::Integer(integer) => write!(f, "{}", integer),
+            Object::BigInt(integer) => write!(f, "{}", integer),
+            Object::Rational(ratio) => write!(f, "{}", ratio),
+            Object::Float(float) => write!(f, "{}", float),
             Object::Str(string) => write!(f, "{}", string),
+            Object::Symbol(name) => write!(f, "'{}", name),
+            Object::IndexMap(map) => {
+                let entries: Vec<String> = map.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "[{}]", entries.join(", "))
+            },
+            Object::Range(range) => write!(f, "{}", range),
+            Object::Iterator(_) => write!(f, "<iterator>"),
+            Object::Error(error) => write!(f, "<error '{}: {}>", error.kind, error.message),
+            Object::Function(function) => write!(f, "|{}| ...", function.params.join(", ")),
+            Object::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
         }
     }
 }
+
+/// A value describing something that went wrong, given to catch.
+#[derive(Debug, Clone)]
+pub struct ErrorValue {
+    /// A symbol name saying what sort of error it is, like io.
+    pub kind: String,
+    pub message: String,
+    /// Any other details, like the path of a missing file.
+    pub payload: IndexMap<Object, Object>,
+}
+
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Spanned<Expression>,
+    pub env: Env,
+    pub doc: Option<String>,
+    /// Whether the body has a yield, so calling it makes a generator.
+    pub is_generator: bool,
+    /// The name it was first bound to, for stack traces.
+    pub name: Option<String>,
+    /// Where the function starts in the source.
+    pub position: usize,
+}
+
+impl Function {
+    /// What stack traces call it: its name, or where it was made if it's never been bound to one.
+    pub fn callee(&self) -> Callee {
+        match &self.name {
+            Some(name) => Callee::Named(name.clone()),
+            None => Callee::Lambda(self.position),
+        }
+    }
+}
+
+// The environment can contain the function itself, so leave it out.
+impl fmt::Debug for Function {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({:?}, {:?})", self.params, self.body)
+    }
+}
+
+pub type BuiltinFn = fn(Vec<Object>) -> RoughResult<Object>;
+
+#[derive(Debug, Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn,
+    pub doc: &'static str,
+}
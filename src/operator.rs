@@ -18,9 +18,13 @@ pub enum Precedence {
     Tenth,
 }
 
-// Haven't implemented anything making this necessary yet (eventually will)
+/// Precedences for tokens that act like infix operators but aren't user definable.
 pub fn reserved_precedences(token: &TokenType) -> Option<Precedence> {
     match token {
+        TokenType::LParen => Some(Precedence::Tenth),
+        TokenType::LBracket | TokenType::SafeLBracket => Some(Precedence::Tenth),
+        // Lower than everything else, so each step of a pipeline can be any expression.
+        TokenType::PipeForward => Some(Precedence::Second),
         _ => None
     }
 }
@@ -1,36 +1,90 @@
 use crate::lexer::Lexer;
+use crate::layout::layout;
 use crate::operator::{ OperatorDefinition, Precedence, reserved_precedences, OperatorType };
-use crate::error::{ RoughError, RoughResult, new_error };
-use crate::ast::Expression;
-use crate::token::{ Token, TokenType };
-use std::iter::{ Peekable };
-
-pub struct Parser<I> where I: Iterator<Item = &Token> {
+use crate::error::{ ErrorKind, RoughError, RoughResult, error_at };
+use crate::ast::{ Expression, Clause };
+use crate::span::{ Span, Spanned };
+use crate::token::{ Token, TokenType, StrPart };
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+pub struct Parser {
     // I want to have it just be the iterator, but the types for iterators are too annoying to deal
     // with right now. Figure it out later.
-    // (What was I using this field for again?)
-    tokens: Vec<Token>,
-    lexer: Peekable<I>,
+    lexer: Peekable<IntoIter<Token>>,
     //lexer: Peekable<Filter<'a>>,
     operators: Vec<OperatorDefinition>,
     errors: Vec<RoughError>,
     // Wouldn't be necessary if I could figure out how to return closures
     cur_token: Option<Token>,
+    /// Doc comments skipped over since the last token.
+    pending_doc: Vec<String>,
+    /// The doc comment right before cur_token, if any.
+    cur_doc: Option<String>,
+    /// Whether there's a line break between cur_token and the next token.
+    line_break: bool,
+    /// Where the last token with any text ended, for the ends of spans.
+    /// Indent and Dedent are left out since they don't cover any code.
+    end: usize,
+    /// Like end, but counting comments and whitespace too, for errors about the source ending.
+    source_end: usize,
+    /// Whether cur_token hasn't really been used yet, after an error at it.
+    /// It's given back by peek and next so the list or block it closes can still use it.
+    held: bool,
+    /// The closing brackets of the lists and groups being parsed, innermost last.
+    closers: Vec<TokenType>,
 }
 
-static empty_early_error: RoughError = RoughError::new("Source ended before making a valid expression".to_string());
+fn empty_early_error(end: Span) -> Vec<RoughError> {
+    vec![RoughError::new(ErrorKind::Parse, "Source ended before making a valid expression".to_string()).or_span(end)]
+}
 
-impl Parser<I> {
-    pub fn new(mut lex: Lexer, operators: Vec<OperatorDefinition>) -> Parser<I> {
+impl Parser {
+    pub fn new(lex: Lexer, operators: Vec<OperatorDefinition>) -> Parser {
         // Annoyances made me do this strange dance. Maybe clean up later
-        let tokens: Vec<Token> = lex.collect();
+        let mut tokens: Vec<Token> = vec![];
+        let mut errors: Vec<RoughError> = vec![];
+        let tab_width = lex.tab_width();
+
+        for result in lex {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(mut errs) => {
+                    // Stands in for the token so the parser doesn't report it missing as well.
+                    let span = errs.iter().find_map(RoughError::span).unwrap_or_default();
+                    tokens.push(Token::new(TokenType::Error, span.start, span.end));
+                    errors.append(&mut errs);
+                },
+            }
+        }
 
-        let parser = Parser {
-            lexer: tokens.iter().peekable(),
-            tokens: tokens,
-            operators: operators,
-            errors: lex.errors,
+        let tokens = match layout(tokens, tab_width) {
+            Ok(tokens) => tokens,
+            Err(mut errs) => {
+                errors.append(&mut errs);
+                vec![]
+            },
+        };
+
+        let mut parser = Parser::from_tokens(tokens, operators);
+        parser.errors = errors;
+        parser
+    }
+
+    /// For parsing tokens that have already been lexed, like the code in an interpolated string.
+    pub fn from_tokens(tokens: Vec<Token>, operators: Vec<OperatorDefinition>) -> Parser {
+        let mut parser = Parser {
+            lexer: tokens.into_iter().peekable(),
+            operators,
+            errors: vec![],
             cur_token: None,
+            pending_doc: vec![],
+            cur_doc: None,
+            line_break: false,
+            end: 0,
+            source_end: 0,
+            held: false,
+            closers: vec![],
         };
 
         parser.next();
@@ -38,36 +92,61 @@ impl Parser<I> {
     }
 
     pub fn current_result(&self) -> RoughResult<Token> {
-        match self.cur_token {
-            Some(token) => Ok(token),
-            None => Err(vec![empty_early_error]),
+        match &self.cur_token {
+            Some(token) => Ok(token.clone()),
+            None => Err(empty_early_error(self.end_span())),
         }
     }
 
     pub fn next(&mut self) {
-        for token in self.lexer {
-            if !ignored(&token) {
-                self.cur_token = Some(token);
-                return ();
+        if self.held {
+            self.held = false;
+            return;
+        }
+
+        self.skip_ignored();
+        self.cur_token = self.lexer.next();
+        self.line_break = false;
+
+        if let Some(token) = &self.cur_token {
+            if !matches!(token.token_type, TokenType::Indent | TokenType::Dedent) {
+                self.end = token.span().end;
+                self.source_end = self.end;
             }
         }
-        self.cur_token = None;
+
+        self.cur_doc = if self.pending_doc.is_empty() {
+            None
+        } else {
+            Some(self.pending_doc.join("\n"))
+        };
+        self.pending_doc.clear();
     }
 
-    fn peek(&self) -> Option<&Token> {
-        while let Some(peek_tok) = self.lexer.peek() {
-            if !ignored(peek_tok) {
-                break;
-            }
-            self.lexer.next();
+    fn peek(&mut self) -> Option<&Token> {
+        if self.held {
+            return self.cur_token.as_ref();
         }
 
+        self.skip_ignored();
         self.lexer.peek()
     }
 
-    fn next_if_equals(&mut self, expected: TokenType) -> bool {
+    /// Doc comments are kept so they can be attached to whatever comes next.
+    fn skip_ignored(&mut self) {
+        while let Some(token) = self.lexer.next_if(ignored) {
+            self.source_end = token.span().end;
+            match token.token_type {
+                TokenType::DocComment(doc) => self.pending_doc.push(doc),
+                TokenType::Newline => self.line_break = true,
+                _ => (),
+            }
+        }
+    }
+
+    fn next_if_equals(&mut self, expected: &TokenType) -> bool {
         let equals = self.peek()
-            .map(|token| token.token_type == expected)
+            .map(|token| token.token_type == *expected)
             .unwrap_or(false);
 
         if equals {
@@ -79,46 +158,236 @@ impl Parser<I> {
     }
 
     fn next_if_equals_result(&mut self, expected: TokenType) -> RoughResult<()> {
-        if !self.next_if_equals(expected) {
-            new_error(format!("Expected next token to be {} but it was {}", expected, self.current_result()?))
+        if !self.next_if_equals(&expected) {
+            let end = self.end_span();
+            match self.peek() {
+                Some(token) => error_at(ErrorKind::Parse, format!("Expected next token to be {} but it was {}", expected, token), token.span()),
+                None => error_at(ErrorKind::Parse, format!("Expected next token to be {} but the source ended", expected), end),
+            }
         } else {
             Ok(())
         }
     }
 
+    /// Starts a list or group at the current token, which close has to be called for.
+    fn open(&mut self, closer: TokenType) -> RoughResult<Span> {
+        let opened = self.current_result()?.span();
+        self.closers.push(closer);
+        Ok(opened)
+    }
+
+    /// Moves past a closing bracket, pointing back at where it was opened if it's missing.
+    /// Anything before the closing bracket, like more entries of a list, is skipped.
+    fn close(&mut self, closer: TokenType, opened: Span) {
+        self.closers.pop();
+        // A held token has already had an error reported at it.
+        let reported = self.held;
+
+        if let Err(errors) = self.next_if_equals_result(closer.clone()) {
+            let errors = if reported {
+                vec![]
+            } else {
+                errors.into_iter().map(|error| error.with_label(opened, "opened here")).collect()
+            };
+            self.skip_to(closer, errors);
+        }
+    }
+
+    /// Keeps the errors and moves past the next closer that isn't part of anything else.
+    /// A closing bracket that nothing is waiting for was probably meant to be this one, so it's used instead.
+    fn skip_to(&mut self, closer: TokenType, errors: Vec<RoughError>) {
+        self.errors.extend(errors);
+        self.synchronise(true);
+
+        let stray = matches!(
+            self.peek().map(|token| token.token_type.clone()),
+            Some(token_type @ (TokenType::RParen | TokenType::RBracket)) if !self.closers.contains(&token_type)
+            );
+        if !self.next_if_equals(&closer) && stray {
+            self.next();
+        }
+    }
+
+    /// Parses an expression, or if that fails, keeps its errors and gives back an
+    /// Error node in its place so parsing can carry on and find any other errors.
+    fn parse_or_recover(&mut self, precedence: Precedence) -> Spanned<Expression> {
+        let start = self.cur_token.as_ref().map(Token::position).unwrap_or(self.source_end);
+        let closers = self.closers.len();
+
+        match self.parse_expression(precedence) {
+            Ok(exp) => exp,
+            Err(errors) => {
+                // Lists and groups the error was in won't be closed now.
+                self.closers.truncate(closers);
+
+                let at_current = self.cur_token.as_ref()
+                    .filter(|token| ends_expression(&token.token_type))
+                    .map(|token| errors.iter().any(|error| error.span() == Some(token.span())))
+                    .unwrap_or(false);
+                self.errors.extend(errors);
+
+                if at_current {
+                    // The error was at something that ends the expression and hasn't been used yet,
+                    // so it's kept for whatever the expression was in to use.
+                    self.held = true;
+                } else {
+                    self.synchronise(false);
+                }
+
+                Spanned::new(Expression::Error, self.span_from(start))
+            },
+        }
+    }
+
+    /// Skips the rest of an expression that failed to parse. It stops before a line break,
+    /// a comma, semicolon or in, or a closing bracket that isn't part of the expression,
+    /// which is where whatever the expression was in can pick up again.
+    /// With to_closer, it only stops at a closing bracket, for skipping the rest of a list.
+    fn synchronise(&mut self, to_closer: bool) {
+        let mut depth = 0;
+
+        // peek has to come first, since it's what finds any line break before the token.
+        while let Some(token_type) = self.peek().map(|token| token.token_type.clone()) {
+            let line_break = self.line_break;
+
+            let stop = if to_closer {
+                matches!(token_type, TokenType::RParen | TokenType::RBracket | TokenType::Dedent)
+            } else {
+                line_break || ends_expression(&token_type)
+            };
+
+            if depth == 0 && stop {
+                break;
+            }
+
+            match token_type {
+                TokenType::LParen | TokenType::LBracket | TokenType::SafeLBracket | TokenType::Indent => depth += 1,
+                TokenType::RParen | TokenType::RBracket | TokenType::Dedent => depth -= 1,
+                _ => (),
+            }
+
+            self.next();
+        }
+    }
+
     pub fn get_errors(&self) -> Vec<RoughError> {
-        self.errors
+        self.errors.clone()
+    }
+
+    /// Right after the last token, for errors about the source ending early.
+    /// It's only used once there are no tokens left, so any comments have already been skipped.
+    fn end_span(&self) -> Span {
+        Span::new(self.source_end, self.source_end)
+    }
+
+    /// An error about the current token.
+    fn error_here<T>(&self, msg: String) -> RoughResult<T> {
+        match &self.cur_token {
+            Some(token) => error_at(ErrorKind::Parse, msg, token.span()),
+            None => error_at(ErrorKind::Parse, msg, self.end_span()),
+        }
+    }
+
+    /// A span from start to the end of the last token parsed.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.end.max(start))
+    }
+
+    /// Parses the whole source, reporting every error found rather than just the first.
+    /// Tokens the lexer couldn't read are parsed as Error nodes.
+    pub fn parse_program(&mut self) -> RoughResult<Spanned<Expression>> {
+        // Nothing is left to parse after a layout error.
+        if self.cur_token.is_none() && !self.errors.is_empty() {
+            return Err(self.get_errors());
+        }
+
+        let exp = self.parse_sequence(true);
+
+        // Anything left over, like an extra ), is skipped so the code after it can still be checked.
+        while let Some(token) = self.peek().cloned() {
+            self.errors.push(RoughError::new(
+                    ErrorKind::Parse,
+                    format!("Unexpected {} after the end of an expression", token)
+                    ).or_span(token.span()));
+
+            self.next();
+            if self.peek().is_none() {
+                break;
+            }
+            self.next();
+            self.parse_sequence(true);
+        }
+
+        if self.errors.is_empty() {
+            Ok(exp)
+        } else {
+            // Lexing errors were found first, so they're put in order with the rest.
+            self.errors.sort_by_key(|error| error.span().map(|span| span.start));
+            Err(self.get_errors())
+        }
     }
 
-    pub fn parse_program(&mut self) -> RoughResult<Expression> {
-        let exp = self.parse_expression(Precedence::First);
+    /// Parses expressions for as long as they're separated by semicolons, or by line breaks
+    /// too with lines. Inside parentheses a line break only wraps the expression, so it's left out.
+    /// A single expression is given back as it is rather than in a Block.
+    /// Expressions that fail to parse are left as Error nodes, with their errors kept in the parser.
+    fn parse_sequence(&mut self, lines: bool) -> Spanned<Expression> {
+        let mut exps = vec![self.parse_or_recover(Precedence::First)];
 
-        /* Might enforce this later.
-        match self.cur_token {
-            Some(_) => RoughResult::new("Only expecting one expression"),
-            None => exp
+        while if lines { self.next_separator() } else { self.next_if_equals(&TokenType::Semicolon) } {
+            self.next();
+            exps.push(self.parse_or_recover(Precedence::First));
         }
-        */
 
-        exp
+        if exps.len() == 1 {
+            exps.remove(0)
+        } else {
+            let span = exps[0].span.to(exps[exps.len() - 1].span);
+            Spanned::new(Expression::Block(exps), span)
+        }
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> RoughResult<Expression> {
+    /// Moves past a semicolon, or checks for a line break before something that
+    /// starts a new expression (so a line starting with else or in carries on).
+    fn next_separator(&mut self) -> bool {
+        if self.next_if_equals(&TokenType::Semicolon) {
+            return true;
+        }
+
+        let starts_expression = match self.peek() {
+            Some(token) => prefix_parse_lookup(token).is_ok(),
+            None => false,
+        };
+        self.line_break && starts_expression
+    }
+
+    /// Each expression's span goes from its first token to its last, including any
+    /// parentheses around it.
+    fn parse_expression(&mut self, precedence: Precedence) -> RoughResult<Spanned<Expression>> {
         let token = self.current_result()?;
         let prefix_parser = prefix_parse_lookup(&token)?;
 
-        let mut exp = prefix_parser(self)?;
+        let node = prefix_parser(self)?;
+        let mut exp = Spanned::new(node, self.span_from(token.position()));
+
+        while let Some(peek_token) = self.peek().cloned() {
+            // An operator at the start of a line begins a new expression instead of carrying on,
+            // apart from |> so long pipelines can have a step on each line.
+            let new_line = self.line_break && peek_token.token_type != TokenType::PipeForward;
+            if new_line || precedence >= self.token_precedence(&peek_token) {
+                break;
+            }
 
-        // If it finds another Ident, it should assume a function call
-        while self.peek().is_some() && precedence < self.token_precedence(&self.peek().unwrap()) {
-            let infix = match infix_parse_lookup(&self.peek().unwrap()) {
+            let infix = match infix_parse_lookup(&peek_token) {
                 Some(infix_op) => infix_op,
                 // Is this ok in this implementation?
                 None => return Ok(exp),
             };
 
             self.next();
-            exp = infix(self, exp)?;
+            let start = exp.span.start;
+            let node = infix(self, exp)?;
+            exp = Spanned::new(node, self.span_from(start));
         }
 
         Ok(exp)
@@ -130,9 +399,11 @@ impl Parser<I> {
         }
 
         // Check given operators
-        if let TokenType::Ident(ident) = token.token_type {
-            if let Some(op) = self.operators.iter().find(|op| op.identifier == ident) {
-                return op.precedence
+        if let TokenType::Operator(ident) = &token.token_type {
+            if let Some(op) = self.operators.iter()
+                .filter(|op| op.op_type != OperatorType::Prefix)
+                .find(|op| op.identifier == *ident) {
+                return op.precedence.clone()
             }
         }
 
@@ -140,113 +411,365 @@ impl Parser<I> {
     }
 }
 
-fn parse_number(parser: &mut Parser<I>) -> RoughResult<Expression> {
-    match &parser.current_result()?.token_type {
-        TokenType::Number(num) => Ok(Expression::Number(num)),
-        other => new_error(format!("Expected Number token, but got {}", other).to_string()),
+fn parse_identifier(parser: &mut Parser) -> RoughResult<Expression> {
+    let name = match parser.current_result()?.token_type {
+        TokenType::Ident(name) => name,
+        other => return parser.error_here(format!("Expected Ident token, but got {}", other)),
+    };
+    let doc = parser.cur_doc.clone();
+
+    if parser.next_if_equals(&TokenType::Assign) {
+        return parse_let_expression(parser, name, doc);
+    }
+
+    Ok(Expression::Ident(name))
+}
+
+fn parse_let_expression(parser: &mut Parser, name: String, doc: Option<String>) -> RoughResult<Expression> {
+    parser.next();
+    let value = parser.parse_or_recover(Precedence::First);
+
+    parser.next_if_equals_result(TokenType::In)?;
+    parser.next();
+    // The rest of the block is in the scope of the name.
+    let body = parser.parse_sequence(true);
+
+    Ok(Expression::Let(name, Box::new(value), Box::new(body), doc))
+}
+
+fn parse_number(parser: &mut Parser) -> RoughResult<Expression> {
+    match parser.current_result()?.token_type {
+        TokenType::Integer(num) => Ok(Expression::Integer(num)),
+        TokenType::Float(num) => Ok(Expression::Float(num)),
+        other => parser.error_here(format!("Expected a number token, but got {}", other)),
+    }
+}
+
+fn parse_string_literal(parser: &mut Parser) -> RoughResult<Expression> {
+    match parser.current_result()?.token_type {
+        TokenType::Str(string) => Ok(Expression::Str(string)),
+        other => parser.error_here(format!("Expected Str token, but got {}", other)),
+    }
+}
+
+fn parse_interpolated_string(parser: &mut Parser) -> RoughResult<Expression> {
+    let token = parser.current_result()?;
+    let parts = match token.token_type.clone() {
+        TokenType::Interpolated(parts) => parts,
+        other => return parser.error_here(format!("Expected an interpolated string token, but got {}", other)),
+    };
+
+    let mut exps = vec![];
+
+    for part in parts {
+        match part {
+            // The text in a string isn't lexed separately, so it has the span of the whole string.
+            StrPart::Literal(string) => exps.push(Spanned::new(Expression::Str(string), token.span())),
+            StrPart::Code(tokens) => {
+                let mut code_parser = Parser::from_tokens(tokens, parser.operators.clone());
+                exps.push(code_parser.parse_expression(Precedence::First)?);
+
+                if !code_parser.errors.is_empty() {
+                    return Err(code_parser.get_errors());
+                }
+
+                if let Some(extra) = code_parser.peek() {
+                    return error_at(ErrorKind::Parse, format!("Unexpected {} in string interpolation", extra), extra.span());
+                }
+            },
+        }
+    }
+
+    Ok(Expression::Interpolation(exps))
+}
+
+/// try body catch name handler, where name is set to the error value in the handler.
+fn parse_try_expression(parser: &mut Parser) -> RoughResult<Expression> {
+    parser.next();
+    let body = parser.parse_expression(Precedence::First)?;
+
+    parser.next_if_equals_result(TokenType::Catch)?;
+    parser.next();
+    let name = match parser.current_result()?.token_type {
+        TokenType::Ident(name) => name,
+        other => return parser.error_here(format!("Expected a name for the error after catch but got {}", other)),
+    };
+
+    parser.next();
+    let handler = parser.parse_expression(Precedence::First)?;
+
+    Ok(Expression::Try(Box::new(body), name, Box::new(handler)))
+}
+
+/// yield value, or yield from iterable. from is only special right after yield.
+fn parse_yield_expression(parser: &mut Parser) -> RoughResult<Expression> {
+    let from = matches!(parser.peek(), Some(token) if token.token_type == TokenType::Ident("from".to_string()));
+    if from {
+        parser.next();
+    }
+
+    parser.next();
+    let value = Box::new(parser.parse_expression(Precedence::First)?);
+
+    if from {
+        Ok(Expression::YieldFrom(value))
+    } else {
+        Ok(Expression::Yield(value))
     }
 }
 
-fn parse_string_literal(parser: &mut Parser<I>) -> RoughResult<Expression> {
-    match &parser.current_result()?.token_type {
-        TokenType::Str(string) => Ok(Expression::Str(string.to_string())),
-        other => new_error(format!("Expected Str token, but got {}", other).to_string()),
+fn parse_literal(parser: &mut Parser) -> RoughResult<Expression> {
+    match parser.current_result()?.token_type {
+        TokenType::Symbol(name) => Ok(Expression::Symbol(name)),
+        TokenType::Nil => Ok(Expression::Nil),
+        TokenType::True => Ok(Expression::Bool(true)),
+        TokenType::False => Ok(Expression::Bool(false)),
+        other => parser.error_here(format!("Expected a literal keyword, but got {}", other)),
     }
 }
 
-fn parse_function_parameters(parser: &mut Parser<I>) -> RoughResult<Vec<String>> {
+fn parse_function_parameters(parser: &mut Parser) -> RoughResult<Vec<String>> {
     let mut params = vec![];
 
+    if parser.next_if_equals(&TokenType::Pipe) {
+        return Ok(params);
+    }
+
     parser.next();
 
     match parser.current_result()?.token_type {
-        TokenType::Ident(name) => params.push(name.to_string()),
-        other => return Err(vec![RoughError::new(format!("Function parameter expected an Ident token but was {}", other))]),
+        TokenType::Ident(name) => params.push(name),
+        other => return parser.error_here(format!("Function parameter expected an Ident token but was {}", other)),
     }
 
-    while parser.peek().map(|tok| tok.token_type) == Some(TokenType::Comma) {
-        parser.next();
+    while parser.next_if_equals(&TokenType::Comma) {
         parser.next();
 
         let current = parser.current_result()?;
 
         if let TokenType::Ident(name) = current.token_type {
-            params.push(name.to_string())
+            params.push(name)
         } else {
-             return new_error(format!("Function parameter expected an Ident token but was {}", current));
+             return parser.error_here(format!("Function parameter expected an Ident token but was {}", current));
         }
     }
 
-    parser.next_if_equals_result(TokenType::Pipe);
+    parser.next_if_equals_result(TokenType::Pipe)?;
 
     Ok(params)
 }
 
-fn parse_function_literal(parser: &mut Parser<I>) -> RoughResult<Expression> {
+fn parse_function_literal(parser: &mut Parser) -> RoughResult<Expression> {
+    let doc = parser.cur_doc.clone();
+    let params = parse_function_parameters(parser)?;
+    parser.next();
+
     Ok(
         Expression::Function(
-            parse_function_parameters(parser)?,
-            Box::new(parser.parse_expression(Precedence::First)?)
+            params,
+            Box::new(parser.parse_expression(Precedence::First)?),
+            doc
             )
       )
 }
 
-fn parse_if_expression(parser: &mut Parser<I>) -> RoughResult<Expression> {
+/// if cond then cons [else alt], where the then can be left out before an indented block.
+/// An else goes with the closest if, and else if chains are just an if as the alt.
+fn parse_if_expression(parser: &mut Parser) -> RoughResult<Expression> {
+    parser.next();
     let cond = parser.parse_expression(Precedence::First)?;
+    let cons = parse_branch(parser, "if")?;
+
+    let alt = if parser.next_if_equals(&TokenType::Else) {
+        parser.next();
+        Some(Box::new(parser.parse_expression(Precedence::First)?))
+    } else {
+        None
+    };
 
-    let cons = parser.parse_expression(Precedence::First)?;
+    Ok(Expression::If(Box::new(cond), Box::new(cons), alt))
+}
 
-    match parser.lexer.peek() {
-        Some(token) if token.token_type == TokenType::Else => {
-            let parsed_else = parser.parse_expression(Precedence::First)?;
-            Ok(Expression::If(Box::new(cond), Box::new(cons), Some(Box::new(parsed_else))))
-        },
-        _ => Ok(Expression::If(Box::new(cond), Box::new(cons), None)),
+/// What comes after a condition: either then and an expression, or an indented block.
+fn parse_branch(parser: &mut Parser, form: &str) -> RoughResult<Spanned<Expression>> {
+    let is_block = matches!(parser.peek(), Some(token) if token.token_type == TokenType::Indent);
+
+    if !parser.next_if_equals(&TokenType::Then) && !is_block {
+        let end = parser.end_span();
+        return match parser.peek() {
+            Some(token) => error_at(ErrorKind::Parse, format!(
+                    "Expected then or an indented block after the condition of {} but got {}",
+                    form,
+                    token
+                    ), token.span()),
+            None => error_at(ErrorKind::Parse, format!("Expected then after the condition of {} but the source ended", form), end),
+        };
+    }
+
+    parser.next();
+    parser.parse_expression(Precedence::First)
+}
+
+/// An indented block of `cond then value` clauses, where the first true cond
+/// gives the value. An else clause at the end is used if none are true.
+/// It's the same as an else if chain, so it's parsed into one.
+fn parse_cond_expression(parser: &mut Parser) -> RoughResult<Expression> {
+    parser.next_if_equals_result(TokenType::Indent)?;
+    let mut clauses: Vec<(Spanned<Expression>, Spanned<Expression>)> = vec![];
+    let mut default = None;
+
+    loop {
+        parser.next();
+        if parser.current_result()?.token_type == TokenType::Else {
+            parser.next();
+            default = Some(parser.parse_expression(Precedence::First)?);
+            break;
+        }
+
+        let cond = parser.parse_expression(Precedence::First)?;
+        let value = parse_branch(parser, "a cond clause")?;
+        clauses.push((cond, value));
+
+        let another = parser.next_separator()
+            || (parser.line_break && matches!(parser.peek(), Some(token) if token.token_type == TokenType::Else));
+        if !another {
+            break;
+        }
     }
+
+    parser.next_if_equals_result(TokenType::Dedent)?;
+
+    // Each if in the chain covers its own clause and the ones after it.
+    let chain = clauses.into_iter().rev().fold(default, |alt, (cond, value)| {
+        let end = alt.as_ref().map(|alt| alt.span).unwrap_or(value.span);
+        let span = cond.span.to(end);
+        Some(Spanned::new(Expression::If(Box::new(cond), Box::new(value), alt.map(Box::new)), span))
+    });
+
+    // The outermost if is given the span of the whole cond by parse_expression.
+    Ok(chain.map(|chain| chain.node).unwrap_or(Expression::Nil))
 }
 
-fn parse_grouped_expression(parser: &mut Parser<I>) -> RoughResult<Expression> {
+/// Its error was already reported by the lexer.
+fn parse_error_token(_parser: &mut Parser) -> RoughResult<Expression> {
+    Ok(Expression::Error)
+}
+
+fn parse_grouped_expression(parser: &mut Parser) -> RoughResult<Expression> {
+    let opened = parser.open(TokenType::RParen)?;
     parser.next();
-    let exp = parser.parse_expression(Precedence::First);
+    let exp = parser.parse_sequence(false);
+
+    parser.close(TokenType::RParen, opened);
+
+    Ok(exp.node)
+}
 
-    let current = parser.current_result()?;
+/// An indented block, which works like a parenthesised expression.
+fn parse_block(parser: &mut Parser) -> RoughResult<Expression> {
+    parser.next();
+    let exp = parser.parse_sequence(true);
 
-    parser.next_if_equals_result(TokenType::RParen)?;
+    if let Err(errors) = parser.next_if_equals_result(TokenType::Dedent) {
+        parser.skip_to(TokenType::Dedent, errors);
+    }
 
-    exp
+    Ok(exp.node)
 }
 
-// TODO actually implement map part.
-fn parse_index_map_literal(parser: &mut Parser<I>) -> RoughResult<Expression> {
-    let mut elems: Vec<Expression> = vec![];
+fn parse_index_map_literal(parser: &mut Parser) -> RoughResult<Expression> {
+    let mut elems: Vec<(Option<Spanned<Expression>>, Spanned<Expression>)> = vec![];
 
-    if parser.peek().map(|tok| tok.token_type) == Some(TokenType::RBracket) {
-        parser.next();
+    if parser.next_if_equals(&TokenType::RBracket) {
         return Ok(Expression::IndexMap(elems))
     }
 
-    parser.next();
-
-    elems.push(parser.parse_expression(Precedence::First)?);
+    let opened = parser.open(TokenType::RBracket)?;
 
-    while parser.peek().map(|tok|tok.token_type == TokenType::Comma).unwrap_or(false) {
-        parser.next();
+    loop {
         parser.next();
+        elems.push(parse_index_map_entry(parser));
 
-        elems.push(parser.parse_expression(Precedence::First)?);
+        if elems.len() == 1 && parser.peek().map(|token| token.token_type == TokenType::For).unwrap_or(false) {
+            let (key, value) = elems.remove(0);
+            return parse_comprehension(parser, key, value, opened);
+        }
+
+        if !parser.next_if_equals(&TokenType::Comma) {
+            break;
+        }
     }
 
-    parser.next_if_equals_result(TokenType::RBracket)?;
+    parser.close(TokenType::RBracket, opened);
 
     Ok(Expression::IndexMap(elems))
 }
 
-fn current_op_def(parser: &mut Parser<I>, op_type: OperatorType) -> RoughResult<OperatorDefinition> {
+/// The for and if clauses after the first entry of an IndexMap literal.
+fn parse_comprehension(
+    parser: &mut Parser,
+    key: Option<Spanned<Expression>>,
+    value: Spanned<Expression>,
+    opened: Span,
+    ) -> RoughResult<Expression> {
+    let mut clauses = vec![];
+
+    loop {
+        if parser.next_if_equals(&TokenType::For) {
+            let mut names = vec![];
+            loop {
+                parser.next();
+                match parser.current_result()?.token_type {
+                    TokenType::Ident(name) => names.push(name),
+                    other => return parser.error_here(format!("Expected a name after for but got {}", other)),
+                }
+
+                if !parser.next_if_equals(&TokenType::Comma) {
+                    break;
+                }
+            }
+
+            parser.next_if_equals_result(TokenType::In)?;
+            parser.next();
+            clauses.push(Clause::For(names, parser.parse_expression(Precedence::First)?));
+        } else if parser.next_if_equals(&TokenType::If) {
+            parser.next();
+            clauses.push(Clause::If(parser.parse_expression(Precedence::First)?));
+        } else {
+            break;
+        }
+    }
+
+    parser.close(TokenType::RBracket, opened);
+
+    let comprehension = Expression::Comprehension(key.map(Box::new), Box::new(value), clauses);
+    // A generator can't stop part way through building the IndexMap.
+    if let Some(span) = comprehension.yield_span() {
+        parser.errors.push(RoughError::new(ErrorKind::Parse, "yield can't be used inside a comprehension".to_string()).or_span(span));
+        return Ok(Expression::Error);
+    }
+
+    Ok(comprehension)
+}
+
+fn parse_index_map_entry(parser: &mut Parser) -> (Option<Spanned<Expression>>, Spanned<Expression>) {
+    let first = parser.parse_or_recover(Precedence::First);
+
+    if parser.next_if_equals(&TokenType::Colon) {
+        parser.next();
+        let value = parser.parse_or_recover(Precedence::First);
+        (Some(first), value)
+    } else {
+        (None, first)
+    }
+}
+
+fn current_op_def(parser: &mut Parser, op_type: OperatorType) -> RoughResult<OperatorDefinition> {
     let op_token: Token = parser.current_result()?;
 
     let op_ident = match op_token.token_type {
-        TokenType::Ident(op_ident) => op_ident,
-        other => return Err(vec![RoughError::new(format!("Should be an Ident token but got {}. Not sure how it even got here.", other))]),
+        TokenType::Operator(op_ident) => op_ident,
+        other => return parser.error_here(format!("Should be an Operator token but got {}. Not sure how it even got here.", other)),
     };
 
     let op_def_option = parser.operators
@@ -255,44 +778,114 @@ fn current_op_def(parser: &mut Parser<I>, op_type: OperatorType) -> RoughResult<
         .find(|op| op.identifier == op_ident);
 
     match op_def_option {
-        Some(op_def) => Ok(*op_def),
-        None => return new_error(format!("Could not find a defined operator that matched {}", op_ident))
+        Some(op_def) => Ok(op_def.clone()),
+        None => parser.error_here(format!("Could not find a defined operator that matched {}", op_ident))
     }
 }
 
 // Might need to figure out function calling here too.
-fn parse_prefix_expression(parser: &mut Parser<I>) -> RoughResult<Expression> {
+fn parse_prefix_expression(parser: &mut Parser) -> RoughResult<Expression> {
     let op_def = current_op_def(parser, OperatorType::Prefix)?;
 
     parser.next();
 
-    let right_exp = parser.parse_expression(op_def.precedence)?;
+    let right_exp = parser.parse_expression(op_def.precedence.clone())?;
     Ok(Expression::Prefix(op_def, Box::new(right_exp)))
 }
 
-fn parse_infix_expression(parser: &mut Parser<I>, left_exp: Expression) -> RoughResult<Expression> {
+fn parse_infix_expression(parser: &mut Parser, left_exp: Spanned<Expression>) -> RoughResult<Expression> {
     let op_def = current_op_def(parser, OperatorType::Infix)?;
 
     parser.next();
 
-    let right_exp = parser.parse_expression(op_def.precedence)?;
+    let right_exp = parser.parse_expression(op_def.precedence.clone())?;
 
     Ok(Expression::Infix(Box::new(left_exp), op_def, Box::new(right_exp)))
 }
 
-type PrefixParseFn = fn(parser: &mut Parser<I>) -> RoughResult<Expression>;
-type InfixParseFn = fn(parser: &mut Parser<I>, left_exp: Expression) -> RoughResult<Expression>;
+fn parse_call_expression(parser: &mut Parser, function: Spanned<Expression>) -> RoughResult<Expression> {
+    let mut args = vec![];
+
+    if parser.next_if_equals(&TokenType::RParen) {
+        return Ok(Expression::Call(Box::new(function), args));
+    }
+
+    let opened = parser.open(TokenType::RParen)?;
+
+    loop {
+        parser.next();
+        args.push(parser.parse_or_recover(Precedence::First));
+
+        if !parser.next_if_equals(&TokenType::Comma) {
+            break;
+        }
+    }
+
+    parser.close(TokenType::RParen, opened);
+
+    Ok(Expression::Call(Box::new(function), args))
+}
+
+/// x |> f(a, b) is f(x, a, b), and x |> f(a, _, b) is f(a, x, b).
+/// Anything else on the right, like x |> f or x |> (make_f(a)), is called with x.
+fn parse_pipe_expression(parser: &mut Parser, left: Spanned<Expression>) -> RoughResult<Expression> {
+    let grouped = matches!(parser.peek(), Some(token) if token.token_type == TokenType::LParen);
+    parser.next();
+    let right = parser.parse_expression(Precedence::Second)?;
+    let span = right.span;
+
+    match right.node {
+        Expression::Call(function, mut args) if !grouped => {
+            let placeholder = Spanned::unspanned(Expression::Ident("_".to_string()));
+            let mut placeholders = args.iter().enumerate().filter(|(_, arg)| **arg == placeholder).map(|(i, _)| i);
+            match (placeholders.next(), placeholders.next()) {
+                (None, _) => args.insert(0, left),
+                (Some(i), None) => args[i] = left,
+                (Some(_), Some(_)) => return error_at(ErrorKind::Parse, "Only one _ can be used in each step of a pipeline".to_string(), span),
+            }
+            Ok(Expression::Call(function, args))
+        },
+        function => Ok(Expression::Call(Box::new(Spanned::new(function, span)), vec![left])),
+    }
+}
+
+fn parse_index_expression(parser: &mut Parser, left_exp: Spanned<Expression>) -> RoughResult<Expression> {
+    let opened = parser.open(TokenType::RBracket)?;
+    let safe = parser.current_result()?.token_type == TokenType::SafeLBracket;
+
+    parser.next();
+    let index = parser.parse_or_recover(Precedence::First);
+
+    parser.close(TokenType::RBracket, opened);
+
+    if safe {
+        Ok(Expression::SafeIndex(Box::new(left_exp), Box::new(index)))
+    } else {
+        Ok(Expression::Index(Box::new(left_exp), Box::new(index)))
+    }
+}
+
+type PrefixParseFn = fn(parser: &mut Parser) -> RoughResult<Expression>;
+type InfixParseFn = fn(parser: &mut Parser, left_exp: Spanned<Expression>) -> RoughResult<Expression>;
 
 fn prefix_parse_lookup(token: &Token) -> RoughResult<PrefixParseFn> {
     let func = match token.token_type {
-        TokenType::Number(_) => parse_number,
+        TokenType::Integer(_) | TokenType::Float(_) => parse_number,
         TokenType::Str(_) => parse_string_literal,
+        TokenType::Interpolated(_) => parse_interpolated_string,
+        TokenType::Symbol(_) | TokenType::Nil | TokenType::True | TokenType::False => parse_literal,
         TokenType::Pipe => parse_function_literal,
-        TokenType::Ident(_) => parse_prefix_expression,
+        TokenType::Ident(_) => parse_identifier,
+        TokenType::Operator(_) => parse_prefix_expression,
         TokenType::If => parse_if_expression,
+        TokenType::Cond => parse_cond_expression,
+        TokenType::Yield => parse_yield_expression,
+        TokenType::Try => parse_try_expression,
         TokenType::LParen => parse_grouped_expression,
+        TokenType::Indent => parse_block,
         TokenType::LBracket => parse_index_map_literal,
-        _ => return Err(vec![RoughError::new(format!("prefix_parse_lookup doesn't have token {}", token).to_string())]),
+        TokenType::Error => parse_error_token,
+        _ => return error_at(ErrorKind::Parse, format!("Expected an expression but got {}", token), token.span()),
     };
 
     Ok(func)
@@ -300,21 +893,28 @@ fn prefix_parse_lookup(token: &Token) -> RoughResult<PrefixParseFn> {
 
 fn infix_parse_lookup(token: &Token) -> Option<InfixParseFn> {
     match token.token_type {
-        TokenType::Ident(_) => Some(parse_infix_expression),
-        // TODO Do later. Make a built in function for now and add this in later.
-        //Token::LBracket => Some(parse_index_expression),
+        TokenType::Operator(_) => Some(parse_infix_expression),
+        TokenType::LParen => Some(parse_call_expression),
+        TokenType::PipeForward => Some(parse_pipe_expression),
+        TokenType::LBracket | TokenType::SafeLBracket => Some(parse_index_expression),
         _ => None
     }
 }
 
-// I suppose not ignoring whitespace might break a lot of code currently.
-// Should deal with this sooner rather than later.
+/// Tokens that can't be part of an expression and come after one,
+/// which parsing picks up from after an error.
+fn ends_expression(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::RParen | TokenType::RBracket | TokenType::Dedent | TokenType::Comma | TokenType::Semicolon | TokenType::In
+    )
+}
+
+// Indentation has already been turned into Indent and Dedent tokens by the
+// layout pass, so the whitespace itself can be skipped.
 fn ignored(token: &Token) -> bool {
-    match token.token_type {
-        TokenType::Comment(_) => true,
-        TokenType::Space => true,
-        TokenType::Tab => true,
-        TokenType::Newline => true,
-        _ => false
-    }
+    matches!(
+        token.token_type,
+        TokenType::Comment(_) | TokenType::DocComment(_) | TokenType::Space | TokenType::Tab | TokenType::Newline
+    )
 }
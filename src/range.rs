@@ -0,0 +1,215 @@
+use crate::object::Object;
+use crate::error::{ ErrorKind, RoughResult, new_error };
+use num_integer::Integer;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{ Hash, Hasher };
+
+/// A lazy range of integers, made with a..b, a..=b and the step builtin.
+/// Only the ends and step are stored, so a range never holds its values in
+/// memory and its length and items are worked out when they're needed.
+#[derive(Debug, Clone)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Range {
+        Range {
+            start,
+            end,
+            step: 1,
+            inclusive,
+        }
+    }
+
+    pub fn with_step(&self, step: i64) -> RoughResult<Range> {
+        if step == 0 {
+            return new_error(ErrorKind::Value, "A range's step can't be 0".to_string());
+        }
+
+        Ok(Range {
+            step,
+            ..self.clone()
+        })
+    }
+
+    /// The number of values. Going the wrong way for the step makes an empty range.
+    /// It's a u128 since -2^63..=2^63-1 has 2^64 values, one more than a u64 can count.
+    pub fn len(&self) -> u128 {
+        let (start, end, step) = (self.start as i128, self.end as i128, self.step as i128);
+        let distance = if step > 0 { end - start } else { start - end };
+        let distance = if self.inclusive { distance + 1 } else { distance };
+
+        if distance <= 0 {
+            0
+        } else {
+            Integer::div_ceil(&distance, &step.abs()) as u128
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: u128) -> Option<i64> {
+        if index < self.len() {
+            Some((self.start as i128 + index as i128 * self.step as i128) as i64)
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> RangeIter {
+        RangeIter {
+            range: self.clone(),
+            index: 0,
+            len: self.len(),
+        }
+    }
+
+    /// The values at the given positions. Positions outside this range are left out,
+    /// and since both are evenly spaced the result is another range.
+    pub fn slice(&self, positions: &Range) -> Range {
+        let (first, last) = match valid_positions(positions, self.len()) {
+            Some(bounds) => bounds,
+            None => return Range::new(self.start, self.start, false),
+        };
+
+        let first = positions.get(first).and_then(|position| self.get(position as u128));
+        let last = positions.get(last).and_then(|position| self.get(position as u128));
+        match (first, last) {
+            (Some(first), Some(last)) => Range {
+                start: first,
+                end: last,
+                step: self.step.saturating_mul(positions.step),
+                inclusive: true,
+            },
+            _ => Range::new(self.start, self.start, false),
+        }
+    }
+
+    /// Ranges with the same values are equal however they were written,
+    /// so 1..4 == 1..=3. Empty ranges have no first value or step.
+    fn canonical(&self) -> (u128, Option<i64>, Option<i64>) {
+        let len = self.len();
+        let first = if len > 0 { Some(self.start) } else { None };
+        let step = if len > 1 { Some(self.step) } else { None };
+        (len, first, step)
+    }
+}
+
+/// The positions in the range that are between 0 and len, for slicing.
+/// Only those are gone through, so a huge range can slice something small.
+pub fn positions_within(positions: &Range, len: usize) -> impl Iterator<Item = usize> + '_ {
+    let bounds = valid_positions(positions, len as u128);
+    bounds.into_iter()
+        .flat_map(|(first, last)| first..=last)
+        .filter_map(move |k| positions.get(k))
+        .map(|position| position as usize)
+}
+
+/// Finds which items of positions (by index) are between 0 and len,
+/// giving the first and last of them if there are any.
+fn valid_positions(positions: &Range, len: u128) -> Option<(u128, u128)> {
+    let count = positions.len() as i128;
+    let (start, step, len) = (positions.start as i128, positions.step as i128, len as i128);
+    if count == 0 || len == 0 {
+        return None;
+    }
+
+    // The indices k where 0 <= start + k * step < len.
+    let (low, high) = if step > 0 {
+        (Integer::div_ceil(&-start, &step), Integer::div_floor(&(len - 1 - start), &step))
+    } else {
+        (Integer::div_ceil(&(len - 1 - start), &step), Integer::div_floor(&-start, &step))
+    };
+    let (low, high) = (low.max(0), high.min(count - 1));
+
+    if low > high {
+        None
+    } else {
+        Some((low as u128, high as u128))
+    }
+}
+
+pub struct RangeIter {
+    range: Range,
+    index: u128,
+    len: u128,
+}
+
+impl Iterator for RangeIter {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if self.index < self.len {
+            self.index += 1;
+            self.range.get(self.index - 1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Makes a range from the operands of .. or ..=.
+pub fn make_range(start: &Object, end: &Object, inclusive: bool) -> RoughResult<Object> {
+    match (start, end) {
+        (Object::Integer(start), Object::Integer(end)) => Ok(Object::Range(Range::new(*start, *end, inclusive))),
+        (start, end) => new_error(ErrorKind::Type, format!(
+                "Ranges can only be made from Integers that fit in 64 bits, not {} and {}",
+                start.type_name(),
+                end.type_name()
+                )),
+    }
+}
+
+impl PartialEq for Range {
+    fn eq(&self, other: &Range) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Range {}
+
+impl PartialOrd for Range {
+    fn partial_cmp(&self, other: &Range) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The same as comparing the values one by one, without going through them all.
+/// Two ranges with the same first value and step only differ in their length.
+impl Ord for Range {
+    fn cmp(&self, other: &Range) -> Ordering {
+        let (left_len, left_first, left_step) = self.canonical();
+        let (right_len, right_first, right_step) = other.canonical();
+
+        left_first.cmp(&right_first)
+            .then_with(|| match (left_step, right_step) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                _ => Ordering::Equal,
+            })
+            .then(left_len.cmp(&right_len))
+    }
+}
+
+impl Hash for Range {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state);
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.inclusive { "..=" } else { ".." };
+        if self.step == 1 {
+            write!(f, "{}{}{}", self.start, op, self.end)
+        } else {
+            write!(f, "step({}{}{}, {})", self.start, op, self.end, self.step)
+        }
+    }
+}
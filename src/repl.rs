@@ -0,0 +1,132 @@
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::evaluator::evaluate;
+use crate::environment::{ Env, Environment };
+use crate::builtin::{ operators, help_text };
+use crate::object::Object;
+use crate::error::{ RoughError, RoughResult };
+use crate::source_map::SourceMap;
+use crate::diagnostic::Renderer;
+use std::io::{ self, BufRead, Write };
+
+const PROMPT: &str = ">> ";
+/// Shown while reading more lines of something that isn't finished.
+const CONTINUE_PROMPT: &str = ".. ";
+
+const COMMANDS: &str = ":help <expression>  Show the documentation of a function
+:quit               Leave the REPL";
+
+/// Lexes, parses and evaluates some source in the given environment.
+pub fn run(source: &str, env: &Env) -> RoughResult<Object> {
+    let mut parser = Parser::new(Lexer::new(source), operators());
+    let exp = parser.parse_program()?;
+    evaluate(&exp, env)
+}
+
+/// Like run, but adds the source to sources first so errors can be shown
+/// with the file, line and column they happened at.
+pub fn run_file(sources: &mut SourceMap, name: &str, source: &str, env: &Env) -> RoughResult<Object> {
+    let offset = sources.add_file(name, source);
+    let mut parser = Parser::new(Lexer::new(source).starting_at(offset), operators());
+    let exp = parser.parse_program()?;
+    evaluate(&exp, env)
+}
+
+/// What to do with the code once it's all been read.
+#[derive(Clone, Copy)]
+enum Command {
+    Eval,
+    Help,
+}
+
+/// Evaluates a line at a time and writes out the result.
+/// Lines starting with : are REPL commands rather than code.
+/// Code that isn't finished at the end of a line, like a doc comment or an
+/// unclosed (, carries on to the next line, until a blank line gives up on it.
+/// Each piece of code is kept in a SourceMap, so errors in functions from
+/// earlier lines can still show where they are.
+pub fn start<R: BufRead, W: Write>(input: R, mut output: W, colour: bool) -> io::Result<()> {
+    let env = Environment::global();
+    let mut sources = SourceMap::new();
+    let mut pending: Option<(Command, String)> = None;
+
+    write!(output, "{}", PROMPT)?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+
+        let code = match pending.take() {
+            Some((command, mut source)) => {
+                let finished = line.trim().is_empty();
+                source.push('\n');
+                source.push_str(line.trim_end());
+                Some((command, source, finished))
+            },
+            None => {
+                let line = line.trim();
+
+                if line == ":quit" {
+                    break;
+                } else if let Some(rest) = line.strip_prefix(":help") {
+                    let rest = rest.trim();
+                    if rest.is_empty() {
+                        writeln!(output, "{}", COMMANDS)?;
+                        None
+                    } else {
+                        Some((Command::Help, rest.to_string(), false))
+                    }
+                } else if line.starts_with(':') {
+                    writeln!(output, "Unknown command {}. Try :help", line)?;
+                    None
+                } else if line.is_empty() {
+                    None
+                } else {
+                    Some((Command::Eval, line.to_string(), false))
+                }
+            },
+        };
+
+        let (command, source, finished) = match code {
+            Some(code) => code,
+            None => {
+                write!(output, "{}", PROMPT)?;
+                output.flush()?;
+                continue;
+            },
+        };
+
+        // The code is only added to sources once it's finished, but it's lexed
+        // from where it'll be so the positions in it are right.
+        let offset = sources.next_start();
+        let mut parser = Parser::new(Lexer::new(&source).starting_at(offset), operators());
+        let parsed = parser.parse_program();
+        if let Err(errors) = &parsed {
+            if !finished && source_ended(errors, offset + source.len()) {
+                pending = Some((command, source));
+                write!(output, "{}", CONTINUE_PROMPT)?;
+                output.flush()?;
+                continue;
+            }
+        }
+
+        sources.add_file("<repl>", &source);
+        let result = parsed.and_then(|exp| evaluate(&exp, &env));
+
+        match (result, command) {
+            (Ok(obj), Command::Eval) => writeln!(output, "{}", obj)?,
+            (Ok(obj), Command::Help) => writeln!(output, "{}", help_text(&obj))?,
+            (Err(errors), _) => write!(output, "{}", Renderer::new(&sources, colour).render_all(&errors))?,
+        }
+
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Whether the code only failed because it ended too soon, so more lines might finish it.
+fn source_ended(errors: &[RoughError], end: usize) -> bool {
+    errors.iter().all(|error| error.span().map(|span| span.start >= end).unwrap_or(false))
+}
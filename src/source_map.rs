@@ -0,0 +1,119 @@
+use std::fmt;
+
+/// The source files that have been run, for turning positions back into
+/// file names, lines and columns.
+///
+/// Each file gets its own range of positions, one after the other, so a
+/// position on its own is enough to find the file it's in. That's what lets
+/// a function made in one file show where it came from when it fails in another.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+    /// The position of the file's first byte.
+    pub start: usize,
+    /// Where each line starts, relative to the start of the file.
+    line_starts: Vec<usize>,
+}
+
+/// A place in a file, with lines and columns counted from 1.
+/// Columns are counted in characters rather than bytes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap {
+            files: vec![],
+        }
+    }
+
+    /// Adds a file and gives back the position it starts at, for the lexer to count from.
+    pub fn add_file(&mut self, name: &str, source: &str) -> usize {
+        let start = self.next_start();
+
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            source: source.to_string(),
+            start,
+            line_starts,
+        });
+
+        start
+    }
+
+    /// The position the next file added will start at, for lexing it before it's added.
+    pub fn next_start(&self) -> usize {
+        // One past the end of the last file, so a position at the very end of
+        // a file (like for "the source ended") is still in that file.
+        self.files.last().map(|file| file.start + file.source.len() + 1).unwrap_or(0)
+    }
+
+    pub fn file(&self, position: usize) -> Option<&SourceFile> {
+        self.files.iter()
+            .rev()
+            .find(|file| file.start <= position && position <= file.start + file.source.len())
+    }
+
+    pub fn location(&self, position: usize) -> Option<Location> {
+        let file = self.file(position)?;
+        let (line, column) = file.line_column(position - file.start);
+
+        Some(Location {
+            file: file.name.clone(),
+            line,
+            column,
+        })
+    }
+
+    /// The location as file:line:column, or just the position if it isn't in any file.
+    pub fn describe(&self, position: usize) -> String {
+        match self.location(position) {
+            Some(location) => location.to_string(),
+            None => position.to_string(),
+        }
+    }
+}
+
+impl SourceFile {
+    /// The line and column of an offset from the start of this file.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+
+        let line_start = self.line_starts[line];
+        let column = self.source.get(line_start..offset)
+            .map(|text| text.chars().count())
+            .unwrap_or(offset - line_start);
+
+        (line + 1, column + 1)
+    }
+
+    /// The text of a line, counting from 1, without its line break.
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map(|next| next - 1).unwrap_or_else(|| self.source.len());
+        self.source[start..end].trim_end_matches('\r')
+    }
+}
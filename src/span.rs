@@ -0,0 +1,66 @@
+use std::fmt;
+use std::ops::Deref;
+
+/// Where something is in the source, as byte offsets from the start of it.
+/// The end is one past the last byte, so an empty span has start == end.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+        }
+    }
+
+    /// From the start of this span to the end of the other one.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A node along with where it came from in the source.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned {
+            node,
+            span,
+        }
+    }
+
+    /// For nodes that weren't parsed from anywhere, like ones made by builtins or in tests.
+    pub fn unspanned(node: T) -> Spanned<T> {
+        Spanned::new(node, Span::default())
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+/// Spans are left out so the same code is equal wherever it was written.
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Spanned<T>) -> bool {
+        self.node == other.node
+    }
+}
@@ -1,3 +1,5 @@
+use crate::span::Span;
+use num_bigint::BigInt;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -7,72 +9,142 @@ pub enum TokenType {
     //EOF,
 
     Ident(String),
-    Number(f64),
+    Integer(BigInt),
+    Float(f64),
     Str(String),
+    Interpolated(Vec<StrPart>),
+    Symbol(String),
     Comment(String),
+    DocComment(String),
     Operator(String),
     LParen,
     RParen,
     LBracket,
+    SafeLBracket,
     Colon,
     Comma,
+    Semicolon,
     Hash,
     RBracket,
     If,
+    Then,
     Else,
+    Cond,
+    Nil,
+    True,
+    False,
     Assign,
     In,
+    For,
+    Yield,
+    Try,
+    Catch,
     Pipe,
+    PipeForward,
     Space,
     Tab,
     Newline,
+    /// Made by the layout pass when a line is indented more than the one before.
+    Indent,
+    /// Made by the layout pass for each indentation level a line goes back out of.
+    Dedent,
+    /// Made by the parser where the lexer couldn't read a token, so the rest can still be parsed.
+    Error,
 }
 
 impl fmt::Display for TokenType {
     fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
-        match &*self {
+        match self {
             //TokenType::Illegal => write!(f, "Illegal"),
             //TokenType::EOF => write!(f, "EOF"),
             TokenType::Ident(name) => write!(f, "{}", name),
-            TokenType::Number(num) => write!(f, "{}", num),
+            TokenType::Integer(num) => write!(f, "{}", num),
+            TokenType::Float(num) => write!(f, "{}", num),
             TokenType::Str(string) => write!(f, "{}", string),
+            TokenType::Interpolated(parts) => {
+                for part in parts {
+                    match part {
+                        StrPart::Literal(string) => write!(f, "{}", string)?,
+                        StrPart::Code(tokens) => {
+                            write!(f, "{{")?;
+                            for token in tokens {
+                                write!(f, "{}", token)?;
+                            }
+                            write!(f, "}}")?;
+                        },
+                    }
+                }
+                Ok(())
+            },
+            TokenType::Symbol(name) => write!(f, "'{}", name),
             TokenType::Comment(comment) => write!(f, "{}", comment),
+            TokenType::DocComment(doc) => write!(f, "{}", doc),
             TokenType::Operator(name) => write!(f, "{}", name),
             TokenType::LParen => write!(f, "("),
             TokenType::RParen => write!(f, ")"),
             TokenType::LBracket => write!(f, "["),
+            TokenType::SafeLBracket => write!(f, "?["),
             TokenType::Colon => write!(f, ":"),
             TokenType::Comma => write!(f, ","),
+            TokenType::Semicolon => write!(f, ";"),
             TokenType::Hash => write!(f, "#"),
             TokenType::RBracket => write!(f, "]"),
             TokenType::If => write!(f, "if"),
+            TokenType::Then => write!(f, "then"),
             TokenType::Else => write!(f, "else"),
+            TokenType::Cond => write!(f, "cond"),
+            TokenType::Nil => write!(f, "nil"),
+            TokenType::True => write!(f, "true"),
+            TokenType::False => write!(f, "false"),
             TokenType::Assign => write!(f, ":="),
             TokenType::In => write!(f, "in"),
+            TokenType::For => write!(f, "for"),
+            TokenType::Yield => write!(f, "yield"),
+            TokenType::Try => write!(f, "try"),
+            TokenType::Catch => write!(f, "catch"),
             TokenType::Pipe => write!(f, "|"),
+            TokenType::PipeForward => write!(f, "|>"),
             TokenType::Space => write!(f, " "),
             // Might want to make this configurable
             TokenType::Tab => write!(f, "    "),
-            TokenType::Newline => write!(f, "\n"),
+            TokenType::Newline => writeln!(f),
+            TokenType::Indent => write!(f, "<indent>"),
+            TokenType::Dedent => write!(f, "<dedent>"),
+            TokenType::Error => write!(f, "<error>"),
         }
     }
 }
 
+/// A piece of a string literal with {...} interpolations in it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    Literal(String),
+    Code(Vec<Token>),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     /// For finding the token later, potentially when showing errors.
-    /// It's the position of the first character scanned in token.
-    position: usize,
+    /// It goes from the first character scanned in the token to just after the last.
+    span: Span,
 }
 
 impl Token {
-    pub fn new (token_type: TokenType, position: usize) -> Token{
+    pub fn new (token_type: TokenType, start: usize, end: usize) -> Token{
         Token {
-            token_type: token_type,
-            position: position,
+            token_type,
+            span: Span::new(start, end),
         }
     }
+
+    pub fn position(&self) -> usize {
+        self.span.start
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 impl fmt::Display for Token {
@@ -0,0 +1,95 @@
+use rough::source_map::{ SourceMap, Location };
+use rough::diagnostic::Renderer;
+use rough::repl::run_file;
+use rough::environment::Environment;
+
+fn location(file: &str, line: usize, column: usize) -> Option<Location> {
+    Some(Location { file: file.to_string(), line, column })
+}
+
+#[test]
+fn test_source_map() {
+    let mut sources = SourceMap::new();
+    assert_eq!(sources.next_start(), 0);
+    let first = sources.add_file("a.rough", "ab\ncd\n");
+    assert_eq!(sources.next_start(), 7);
+    let second = sources.add_file("b.rough", "é = x\r\ny");
+
+    assert_eq!(first, 0);
+    assert_eq!(second, 7);
+
+    let tests = [
+        (0, location("a.rough", 1, 1)),
+        (1, location("a.rough", 1, 2)),
+        (3, location("a.rough", 2, 1)),
+        (6, location("a.rough", 3, 1)),
+        (7, location("b.rough", 1, 1)),
+        // Columns count characters, so the é is one column even though it's two bytes.
+        (10, location("b.rough", 1, 3)),
+        (15, location("b.rough", 2, 1)),
+        (16, location("b.rough", 2, 2)),
+        (100, None),
+    ];
+
+    for (position, expected) in tests.iter() {
+        assert_eq!(sources.location(*position), *expected, "Position {}", position);
+    }
+
+    assert_eq!(sources.file(15).map(|file| file.line(1)), Some("é = x"));
+    assert_eq!(sources.describe(3), "a.rough:2:1");
+    assert_eq!(sources.describe(100), "100");
+}
+
+/// Runs each source as its own file and renders the errors.
+fn render(files: &[(&str, &str)], colour: bool) -> String {
+    let mut sources = SourceMap::new();
+    let env = Environment::global();
+    let mut output = String::new();
+
+    for (name, source) in files.iter() {
+        if let Err(errors) = run_file(&mut sources, name, source, &env) {
+            output.push_str(&Renderer::new(&sources, colour).render_all(&errors));
+        }
+    }
+
+    output
+}
+
+#[test]
+fn test_rendering() {
+    let tests = [
+        ("1 + nope", "Error[E0003]: Identifier nope not found\n --> main.rough:1:5\n  |\n1 | 1 + nope\n  |     ^^^^\n"),
+        ("x := 1 in\nx +\n    \"a\"", "Error[E0004]: Operator + can't be used on Integer and String\n --> main.rough:2:1\n  |\n2 | x +\n  | ^^^\n"),
+        ("\"\\q\"", "Error[E0001]: Unknown escape \\q\n --> main.rough:1:2\n  |\n1 | \"\\q\"\n  |  ^^\n  = note: the escapes are \\\" \\{ \\} \\\\ \\n \\t \\r \\0 \\xNN and \\u{NNNN}\n"),
+        ("x := 1 in\n\"bad \\q escape\"", "Error[E0001]: Unknown escape \\q\n --> main.rough:2:6\n  |\n2 | \"bad \\q escape\"\n  |      ^^\n  = note: the escapes are \\\" \\{ \\} \\\\ \\n \\t \\r \\0 \\xNN and \\u{NNNN}\n"),
+        ("x := 12ab in x", "Error[E0001]: Unexpected a in number literal\n --> main.rough:1:8\n  |\n1 | x := 12ab in x\n  |        ^\n"),
+        ("(1 2)", "Error[E0002]: Expected next token to be ) but it was 2\n --> main.rough:1:4\n  |\n1 | (1 2)\n  |    ^\n ::: main.rough:1:1\n  |\n1 | (1 2)\n  | - opened here\n"),
+        ("f(1,\n  2", "Error[E0002]: Expected next token to be ) but the source ended\n --> main.rough:2:4\n  |\n2 |   2\n  |    ^\n ::: main.rough:1:2\n  |\n1 | f(1,\n  |  - opened here\n"),
+        ("f := |a, b| a + b in\nf(1)", "Traceback (most recent call last):\n  f, called at main.rough:2:1\nError[E0005]: Function expected 2 arguments but got 1\n --> main.rough:2:1\n  |\n2 | f(1)\n  | ^^^^\n ::: main.rough:1:6\n  |\n1 | f := |a, b| a + b in\n  |      ------------ defined here\n"),
+        ("len(ln)", "Error[E0003]: Identifier ln not found\n --> main.rough:1:5\n  |\n1 | len(ln)\n  |     ^^\n  = help: did you mean len?\n"),
+        ("if a\n\tb", "Error[E0003]: Identifier a not found\n --> main.rough:1:4\n  |\n1 | if a\n  |    ^\n"),
+        ("f := |x|\n\tx +  nope\nin f(1)", "Traceback (most recent call last):\n  f, called at main.rough:3:4\nError[E0003]: Identifier nope not found\n --> main.rough:2:7\n  |\n2 | \tx +  nope\n  | \t     ^^^^\n"),
+        ("x := 1 in\n\n\n\n\n\n\n\n\nnope", "Error[E0003]: Identifier nope not found\n  --> main.rough:10:1\n   |\n10 | nope\n   | ^^^^\n"),
+        ("map(1..2, |x| x[0]) |> collect", "Traceback (most recent call last):\n  collect, called at main.rough:1:1\n  <lambda at main.rough:1:11>, called by a builtin\nError[E0004]: Can't index into Integer\n --> main.rough:1:15\n  |\n1 | map(1..2, |x| x[0]) |> collect\n  |               ^^^^\n"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(render(&[("main.rough", given)], false), *expected, "Rendering {:?}", given);
+    }
+}
+
+#[test]
+fn test_rendering_across_files() {
+    // Errors in the second file are found in it, not the first.
+    let output = render(&[("a.rough", "1"), ("b.rough", "2 +\n nope")], false);
+    assert_eq!(output, "Error[E0003]: Identifier nope not found\n --> b.rough:2:2\n  |\n2 |  nope\n  |  ^^^^\n");
+}
+
+#[test]
+fn test_colour() {
+    let output = render(&[("main.rough", "nope")], true);
+    assert_eq!(
+        output,
+        "\x1b[1;31mError[E0003]\x1b[0m: Identifier nope not found\n \x1b[1;34m-->\x1b[0m main.rough:1:1\n  \x1b[1;34m|\x1b[0m\n\x1b[1;34m1\x1b[0m \x1b[1;34m|\x1b[0m nope\n  \x1b[1;34m|\x1b[0m \x1b[1;31m^^^^\x1b[0m\n"
+    );
+}
@@ -0,0 +1,581 @@
+mod test_utils;
+
+use test_utils::{ eval, eval_to_string };
+use rough::builtin::help_text;
+use rough::error::ErrorKind;
+use rough::evaluator::MAX_CALL_DEPTH;
+use std::thread;
+
+#[test]
+fn test_nil() {
+    let tests = [
+        ("nil", "nil"),
+        ("if false then 1", "nil"),
+        ("nil == nil", "true"),
+        ("nil == false", "false"),
+        ("nil != 0", "true"),
+        ("[1, nil]", "[0: 1, 1: nil]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_safe_index() {
+    let tests = [
+        ("[\"a\": 1][\"a\"]", "1"),
+        ("[\"a\": 1]?[\"b\"]", "nil"),
+        ("nil?[\"b\"]", "nil"),
+        ("conf := [\"server\": [\"port\": 80]] in conf?[\"server\"]?[\"port\"]", "80"),
+        ("conf := [\"server\": [\"port\": 80]] in conf?[\"client\"]?[\"port\"]", "nil"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_index_errors() {
+    let tests = [
+        "[\"a\": 1][\"b\"]",
+        "nil[\"b\"]",
+        "1?[\"b\"]",
+    ];
+
+    for given in tests.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_equality_and_ordering() {
+    let tests = [
+        ("[1, \"a\"] == [1, \"a\"]", "true"),
+        ("[\"a\": 1, \"b\": 2] == [\"b\": 2, \"a\": 1]", "false"),
+        ("'foo == 'foo", "true"),
+        ("'foo == \"foo\"", "false"),
+        ("(0.0 / 0) == (0.0 / 0)", "true"),
+        ("0 == -0", "true"),
+        ("nil < false", "true"),
+        ("true < 0", "true"),
+        ("1 < \"a\"", "true"),
+        ("\"a\" < 'a", "true"),
+        ("\"b\" > \"a\"", "true"),
+        ("sort([3, 0.0 / 0, 1, \"x\", nil, -2])", "[0: nil, 1: -2, 2: 1, 3: 3, 4: NaN, 5: x]"),
+        ("sort_keys([\"b\": 1, \"a\": 2])", "[a: 2, b: 1]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_index_map_keys() {
+    let tests = [
+        ("[[1, 2]: \"list\"][[1, 2]]", "list"),
+        ("['a: 1, true: 2]['a]", "1"),
+        ("[(0.0 / 0): \"nan\"][0.0 / 0]", "nan"),
+        ("[0: \"zero\"][-0]", "zero"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_numeric_tower() {
+    let tests = [
+        ("9007199254740993", "9007199254740993"),
+        ("9007199254740992 + 1", "9007199254740993"),
+        ("9223372036854775807 + 1", "9223372036854775808"),
+        ("-9223372036854775807 - 1 - 1", "-9223372036854775809"),
+        ("(9223372036854775807 + 1) - 1", "9223372036854775807"),
+        ("4294967296 * 4294967296", "18446744073709551616"),
+        ("6 / 3", "2"),
+        ("7 / 2", "3.5"),
+        ("7 // 2", "3"),
+        ("-7 // 2", "-4"),
+        ("7 % 3", "1"),
+        // % floors like //, so the remainder has the sign of the right side.
+        ("-7 % 2", "1"),
+        ("7 % -2", "-1"),
+        ("-7 % -2", "-1"),
+        ("(-7 // 2) * 2 + -7 % 2", "-7"),
+        ("(-9223372036854775807 - 1) % -1", "0"),
+        ("-9223372036854775809 % 10", "1"),
+        ("rational(-7, 2) % 2", "1/2"),
+        ("rational(7, 2) % -2", "-1/2"),
+        ("-7.5 % 2", "0.5"),
+        ("7.5 % -2", "-0.5"),
+        ("1000000000000000000000000000001 / 3", "333333333333333300000000000000"),
+        ("rational(1, 3) + rational(1, 6)", "1/2"),
+        ("rational(1, 3) * 3", "1"),
+        ("rational(1, 2) + 0.25", "0.75"),
+        ("1 + 0.5", "1.5"),
+        ("1.5 * 2", "3"),
+        ("int(-2.7)", "-2"),
+        ("float(rational(1, 4))", "0.25"),
+        ("1 == 1.0", "true"),
+        ("rational(1, 2) == 0.5", "true"),
+        ("9007199254740993 == 9007199254740992.0", "false"),
+        ("9007199254740993 > 9007199254740992.0", "true"),
+        ("[1: \"one\"][1.0]", "one"),
+        ("[rational(4, 2): \"two\"][2]", "two"),
+        ("sort([2.5, 1, rational(3, 2), 9223372036854775808])", "[0: 1, 1: 3/2, 2: 2.5, 3: 9223372036854775808]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_division_by_zero() {
+    let tests = ["1 / 0", "1 // 0", "1 % 0", "rational(1, 0)", "rational(1, 2) / 0"];
+
+    for given in tests.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+
+    assert_eq!(eval_to_string("1.0 / 0"), "inf");
+
+    // Too big for a Float, rather than silently being inf.
+    let huge = format!("1{}1 / 3", "0".repeat(399));
+    assert!(eval(&huge).is_err(), "Expected {} to be an error", huge);
+}
+
+#[test]
+fn test_string_interpolation() {
+    let tests = [
+        ("name := \"world\" in \"hello {name}\"", "hello world"),
+        ("count := 2 in \"{count + 1} files\"", "3 files"),
+        ("\"{1}{2}\"", "12"),
+        ("\"{[\"a\": nil][\"a\"]} and {7 / 2}\"", "nil and 3.5"),
+        ("\"nested {\"inner {1 + 1}\"}\"", "nested inner 2"),
+        ("\"map {[1, 'a]}\"", "map [0: 1, 1: 'a]"),
+        ("\"\\{not code\\}\"", "{not code}"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_bad_string_interpolation() {
+    let tests = ["\"{}\"", "\"{ }\"", "\"{1 2}\"", "\"{1\"", "\"{nope}\""];
+
+    for given in tests.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_help_text() {
+    let tests = [
+        ("## Adds one.\ninc := |x| x + 1 in inc", "|x|\nAdds one."),
+        ("## Adds.\nadd := |x, y| x + y in add", "|x, y|\nAdds."),
+        ("f := |x| x in f", "|x|\nNo documentation."),
+        ("## The doc of the literal wins.\nf := ## Inner.\n|x| x in f", "|x|\nInner."),
+        ("len", "len (builtin)\nThe number of characters in a string, entries in an IndexMap or values in a range."),
+        ("1", "Integer has no documentation."),
+    ];
+
+    for (given, expected) in tests.iter() {
+        match eval(given) {
+            Ok(obj) => assert_eq!(help_text(&obj), *expected, "Evaluating {}", given),
+            Err(errors) => panic!("{} gave errors: {:?}", given, errors),
+        }
+    }
+}
+
+#[test]
+fn test_unicode() {
+    let tests = [
+        ("größe := 3 in größe * 2", "6"),
+        ("cafe\u{301} := 1 in caf\u{e9}", "1"),
+        ("inc := |x| x + 1 in double := |x| x * 2 in (inc ∘ double)(5)", "11"),
+        ("(len ∘ sort)([3, 1])", "2"),
+        ("1 ≠ 2", "true"),
+        ("2 ≤ 2", "true"),
+        ("1 ≥ 2", "false"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    assert!(eval("1 ∘ len").is_err());
+}
+
+#[test]
+fn test_layout() {
+    let tests = [
+        ("if 1 > 2\n    'yes\nelse\n    'no", "'no"),
+        ("x := 5 in\nif x > 2\n    y := x * 2 in\n    y + 1\nelse\n    0", "11"),
+        ("total :=\n    a := 1 in\n    a + 2\nin total * 2", "6"),
+        ("f := |x|\n    y := x + 1 in\n    y * y\nin f(2)", "9"),
+        ("fact := |n|\n\tif n < 2\n\t\t1\n\telse\n\t\tn * fact(n - 1)\nin fact(5)", "120"),
+        ("sum := (1 +\n  2 +\n      3) in sum", "6"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    assert!(eval("x := 1 in\n    x\n  + 1").is_err());
+}
+
+#[test]
+fn test_sequences() {
+    let tests = [
+        ("1; 2; 3", "3"),
+        ("1\n2\n3\n", "3"),
+        ("x := 2 in\nprint(x)\nx * 3", "6"),
+        ("x := 1 in\nx\n- 1", "-1"),
+        ("x := 1 -\n1 in x", "0"),
+        ("f := |x|\n    print(x)\n    x + 1\nin f(1); f(2)", "3"),
+        ("if false\n    1\n2", "2"),
+        ("(1; 2) + 3", "5"),
+        ("f := |x| (print(x); x + 1) in f(1)", "2"),
+        ("(1\n- 1)", "0"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_conditionals() {
+    let tests = [
+        ("if 1 < 2 then 'a else 'b", "'a"),
+        ("x := 5 in if x < 0 then 'neg else if x == 0 then 'zero else 'pos", "'pos"),
+        ("sign := |x|\n    cond\n        x < 0 then -1\n        x == 0 then 0\n        else 1\nin [sign(-5), sign(0), sign(5)]", "[0: -1, 1: 0, 2: 1]"),
+        ("cond\n    false then 1", "nil"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_ranges() {
+    let tests = [
+        ("1..4", "1..4"),
+        ("collect(1..4)", "[0: 1, 1: 2, 2: 3]"),
+        ("collect(1..=4)", "[0: 1, 1: 2, 2: 3, 3: 4]"),
+        ("collect(4..1)", "[]"),
+        ("collect(step(1..10, 3))", "[0: 1, 1: 4, 2: 7]"),
+        ("collect(step(10..=0, -5))", "[0: 10, 1: 5, 2: 0]"),
+        ("n := 3 in collect(0..n + 1)", "[0: 0, 1: 1, 2: 2, 3: 3]"),
+        ("len(0..1_000_000_000_000)", "1000000000000"),
+        ("len(step(0..=10, 2))", "6"),
+        ("(0..1_000_000_000_000)[999_999_999_999]", "999999999999"),
+        ("(5..10)?[5]", "nil"),
+        ("(5..10)?[-1]", "nil"),
+        ("(0..1_000_000)[10..13]", "10..=12"),
+        ("(0..100)[step(0..100, 25)]", "step(0..=75, 25)"),
+        ("collect((0..10)[step(5..= -5, -2)])", "[0: 5, 1: 3, 2: 1]"),
+        ("[10, 20, 30, 40][1..3]", "[1: 20, 2: 30]"),
+        ("['a: 1, 'b: 2][1..1_000_000_000_000]", "['b: 2]"),
+        ("\"hello\"[1..=3]", "ell"),
+        ("\"hello\"[step(4..=0, -1)]", "olleh"),
+        ("1..4 == 1..=3", "true"),
+        ("3..3 == 5..2", "true"),
+        ("step(1..2, 5) == 1..2", "true"),
+        ("1..3 < 1..4", "true"),
+        ("[1..3: 'a] == [1..=2: 'a]", "true"),
+        ("len(-9223372036854775807 - 1 ..= 9223372036854775807)", "18446744073709551616"),
+        ("collect(take(-9223372036854775807 - 1 ..= 9223372036854775807, 2))", "[0: -9223372036854775808, 1: -9223372036854775807]"),
+        ("(-9223372036854775807 - 1 ..= 9223372036854775807)[9223372036854775807]", "-1"),
+        ("(-9223372036854775807 - 1 ..= 9223372036854775807) == (-9223372036854775807 - 1 ..= 0)", "false"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    for given in ["1.5..3", "step(1..3, 0)", "(1..3)['a]", "(1..3)[5]"].iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_iterators() {
+    let tests = [
+        ("collect(map(1..4, |x| x * 10))", "[0: 10, 1: 20, 2: 30]"),
+        ("collect(filter([1, 2, 3, 4], |x| x % 2 == 0))", "[0: 2, 1: 4]"),
+        ("collect(map(\"abc\", |c| c + c))", "[0: aa, 1: bb, 2: cc]"),
+        ("collect(['a: 1, 'b: 2])", "[0: 1, 1: 2]"),
+        ("collect(take(map(0..1_000_000_000_000, |x| x * 2), 3))", "[0: 0, 1: 2, 2: 4]"),
+        ("collect(take(filter(0..1_000_000_000_000, |x| x > 5), 2))", "[0: 6, 1: 7]"),
+        ("collect(zip(1..10, \"ab\"))", "[0: [0: 1, 1: a], 1: [0: 2, 1: b]]"),
+        ("collect(enumerate(['x, 'y]))", "[0: [0: 0, 1: 'x], 1: [0: 1, 1: 'y]]"),
+        ("collect(chain(1..3, [5], \"z\"))", "[0: 1, 1: 2, 2: 5, 3: z]"),
+        ("collect(chain())", "[]"),
+        ("fold(1..=100, 0, |sum, x| sum + x)", "5050"),
+        ("fold(0..100_000, 0, |sum, x| sum + x)", "4999950000"),
+        ("fold(iter(\"rough\"), \"\", |acc, c| c + acc)", "hguor"),
+        // Copies of an iterator share their place.
+        ("it := iter(1..10) in\ncollect(take(it, 2))\ncollect(take(it, 2))", "[0: 3, 1: 4]"),
+        ("it := iter([1]) in\ncollect(it)\ncollect(it)", "[]"),
+        ("it := iter(1..3) in it == it", "true"),
+        ("iter(1..3) == iter(1..3)", "false"),
+        ("map(1..3, |x| x)", "<iterator>"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    let errors = [
+        "iter(1)",
+        "collect(map(1..3, |x| x + nope))",
+        "fold([1, 'a], 0, |sum, x| sum + x)",
+        "take(1..3, -1)",
+        "lines(\"/this/does/not/exist\")",
+    ];
+
+    for given in errors.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_lines() {
+    let path = std::env::temp_dir().join("rough_test_lines.txt");
+    std::fs::write(&path, "first\nsecond\nthird\n").unwrap();
+
+    let source = format!("collect(take(lines({:?}), 2))", path.to_str().unwrap());
+    assert_eq!(eval_to_string(&source), "[0: first, 1: second]");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_generators() {
+    let tests = [
+        ("gen := ||\n    yield 1\n    yield 2\nin collect(gen())", "[0: 1, 1: 2]"),
+        ("gen := |x| yield x * 2 in collect(gen(21))", "[0: 42]"),
+        ("gen := |n|\n    if n > 0\n        yield 'positive\n    else\n        yield 'other\n    yield n\nin collect(gen(3))", "[0: 'positive, 1: 3]"),
+        ("gen := ||\n    x := (yield 1) in\n    yield x\nin collect(gen())", "[0: 1, 1: nil]"),
+        ("gen := ||\n    yield \"a{yield 1}b\"\nin collect(gen())", "[0: 1, 1: anilb]"),
+        ("gen := ||\n    yield [yield 1, 'k: (yield 2)]\nin collect(gen())", "[0: 1, 1: 2, 2: [0: nil, 'k: nil]]"),
+        ("gen := ||\n    yield (yield 1) == nil\nin collect(gen())", "[0: 1, 1: true]"),
+        ("gen := ||\n    yield from 1..4\n    yield from \"ab\"\nin collect(gen())", "[0: 1, 1: 2, 2: 3, 3: a, 4: b]"),
+        ("squares := |n| yield from map(1..=n, |i| i * i) in fold(squares(4), 0, |a, b| a + b)", "30"),
+        // Generators are lazy, so infinite ones are fine.
+        ("naturals := |n|\n    yield n\n    yield from naturals(n + 1)\nin collect(take(naturals(0), 4))", "[0: 0, 1: 1, 2: 2, 3: 3]"),
+        ("naturals := |n|\n    yield n\n    yield from naturals(n + 1)\nin fold(take(naturals(1), 50_000), 0, |a, b| a + b)", "1250025000"),
+        ("gen := ||\n    yield 1\n    nope\nin collect(take(gen(), 1))", "[0: 1]"),
+        ("gen := || yield 1 in g := gen() in\ncollect(g)\ncollect(g)", "[]"),
+        // A function inside a generator isn't a generator itself.
+        ("gen := ||\n    f := |x| x + 1 in\n    yield f(1)\nin collect(gen())", "[0: 2]"),
+        ("gen := ||\n    f := || yield 'inner in\n    yield from f()\n    yield 'outer\nin collect(gen())", "[0: 'inner, 1: 'outer]"),
+        ("collect(map(iter([1, 2]), |x| collect((|| yield x)())))", "[0: [0: 1], 1: [0: 2]]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    let errors = [
+        "yield 1",
+        "gen := ||\n    yield 1\n    nope\nin collect(gen())",
+        "gen := || yield from 5 in collect(gen())",
+        "g := (|| yield from g)() in collect(g)",
+    ];
+
+    for given in errors.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_comprehensions() {
+    let tests = [
+        ("[x * 2 for x in [1, 2, 3]]", "[0: 2, 1: 4, 2: 6]"),
+        ("[x for x in 1..10 if x % 3 == 0]", "[0: 3, 1: 6, 2: 9]"),
+        ("[c + c for c in \"ab\"]", "[0: aa, 1: bb]"),
+        ("[v: k for k, v in ['a: 1, 'b: 2]]", "[1: 'a, 2: 'b]"),
+        ("[k for k, v in ['a: 1, 'b: 2, 'c: 3] if v > 1]", "[0: 'b, 1: 'c]"),
+        ("[a * b for a, b in zip(1..4, 10..40)]", "[0: 10, 1: 22, 2: 36]"),
+        ("[x + y for x in [10, 20] for y in 1..3]", "[0: 11, 1: 12, 2: 21, 3: 22]"),
+        ("[x for x in [] if nope]", "[]"),
+        ("[x % 2: x for x in 1..6]", "[1: 5, 0: 4]"),
+        ("[x for x in take(map(0..1_000_000_000, |x| x * x), 3)]", "[0: 0, 1: 1, 2: 4]"),
+        ("fs := [|| x for x in 1..4] in [f() for f in fs]", "[0: 1, 1: 2, 2: 3]"),
+        ("x := 'outer in\n[x for x in 1..3]\nx", "'outer"),
+        ("[\n    x * x\n    for x in 1..4\n    if x > 1\n]", "[0: 4, 1: 9]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    let errors = [
+        "[x for x in 5]",
+        "[a + b for a, b in 1..3]",
+        "[a for a, b, c in [[1, 2]]]",
+        "gen := || [yield x for x in 1..3] in collect(gen())",
+    ];
+
+    for given in errors.iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_pipelines() {
+    let tests = [
+        ("[3, 1, 2] |> sort", "[0: 1, 1: 2, 2: 3]"),
+        ("1..4 |> map(|x| x * x) |> collect", "[0: 1, 1: 4, 2: 9]"),
+        ("1..=10 |> filter(|x| x % 2 == 0) |> fold(0, |a, b| a + b)", "30"),
+        ("5 |> |x| x + 1", "6"),
+        ("sub := |a, b| a - b in 10 |> sub(1)", "9"),
+        ("sub := |a, b| a - b in 10 |> sub(1, _)", "-9"),
+        ("adder := |n| |x| x + n in 10 |> (adder(5))", "15"),
+        ("1 + 2 |> |x| x * 10", "30"),
+        ("x := 1 |> |x| x + 1 in x", "2"),
+        ("\"hello\"\n    |> map(|c| c + c)\n    |> collect\n|> len", "5"),
+        ("1..4\n|> collect", "[0: 1, 1: 2, 2: 3]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    for given in ["1 |> 2", "f := |a, b| a in 1 |> f(_, _)"].iter() {
+        assert!(eval(given).is_err(), "Expected {} to be an error", given);
+    }
+}
+
+#[test]
+fn test_try_catch() {
+    let tests = [
+        ("try 1 catch e 2", "1"),
+        ("try nope catch e e['message]", "Identifier nope not found"),
+        ("try 1 / 0 catch e e['kind]", "'value"),
+        ("try nope catch e e['kind]", "'name"),
+        ("try 1 + \"a\" catch e e['kind]", "'type"),
+        ("try [1: 2][3] catch e e['kind]", "'index"),
+        ("try (|x| x)(1, 2) catch e e['kind]", "'arity"),
+        ("try 1 / 0 catch e e", "<error 'value: Division by zero>"),
+        ("try raise(\"bad\") catch e e", "<error 'error: bad>"),
+        ("try raise('config, \"no port\", ['key: \"port\"]) catch e [e['kind], e['payload]['key]]", "[0: 'config, 1: port]"),
+        ("try lines(\"/this/does/not/exist\") catch e e['kind]", "'io"),
+        ("try lines(\"/this/does/not/exist\") catch e e['payload]['path]", "/this/does/not/exist"),
+        ("try\n    try raise('inner, \"x\") catch e raise(e)\ncatch outer\n    outer['kind]", "'inner"),
+        ("try\n    try raise('a, \"x\") catch e raise('b, \"y\")\ncatch e\n    e['kind]", "'b"),
+        ("f := |x| if x > 2 then raise('big, \"too big\") else x in [try f(x) catch e e['kind] for x in 1..5]", "[0: 1, 1: 2, 2: 'big, 3: 'big]"),
+        ("e := 'outer in\ntry 1 catch e 2\ne", "'outer"),
+        ("try raise('a, \"x\") catch e e == (try raise('a, \"x\") catch e e)", "true"),
+        ("gen := ||\n    yield 1\n    try\n        yield 2\n        raise('stop, \"\")\n        yield 'skipped\n    catch e\n        yield e['kind]\n    yield 3\nin collect(gen())", "[0: 1, 1: 2, 2: 'stop, 3: 3]"),
+        ("gen := ||\n    try yield from map([1, 0], |x| 1 // x) catch e yield 'caught\nin collect(gen())", "[0: 1, 1: 'caught]"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(eval_to_string(given), *expected, "Evaluating {}", given);
+    }
+
+    let errors = [
+        ("raise(\"bad\")", "bad"),
+        ("raise('io, \"missing\")", "missing"),
+        ("try nope catch e raise(e)", "Identifier nope not found"),
+        ("raise(1)", "raise expects a message, or a kind symbol, message and maybe a payload IndexMap, but got Integer"),
+    ];
+
+    for (given, expected) in errors.iter() {
+        match eval(given) {
+            Err(errors) => assert_eq!(errors[0].to_string(), *expected, "Evaluating {}", given),
+            Ok(obj) => panic!("Expected {} to be an error but got {}", given, obj),
+        }
+    }
+}
+
+#[test]
+fn test_stack_traces() {
+    let traceback = |source: &str| match eval(source) {
+        Err(errors) => errors[0].traceback(),
+        Ok(obj) => panic!("Expected {} to be an error but got {}", source, obj),
+    };
+
+    let tests = [
+        ("nope", ""),
+        ("f := |x| nope in f(1)", "Traceback (most recent call last):\n  f, called at 17\n"),
+        ("f := |x| x + nope in\ng := |x| f(x) in\ng(1)", "Traceback (most recent call last):\n  g, called at 38\n  f, called at 30\n"),
+        ("(|x| nope)(1)", "Traceback (most recent call last):\n  <lambda at 0>, called at 0\n"),
+        ("map(1..3, |x| x // 0) |> collect", "Traceback (most recent call last):\n  collect, called at 0\n  <lambda at 10>, called by a builtin\n"),
+        ("f := |x| if x == 0 then nope else f(x - 1) in f(3)", "Traceback (most recent call last):\n  f, called at 46\n  f, called at 34\n  [Previous line repeated 2 more times]\n"),
+        ("gen := ||\n    yield 1\n    yield nope\nin collect(gen())", "Traceback (most recent call last):\n  collect, called at 40\n  gen, called at 48\n"),
+        ("g := |x| raise('bad, \"x\") in try g(1) catch e raise(e)", "Traceback (most recent call last):\n  raise, called at 46\n"),
+        ("gen := |n| yield nope in collect(gen(1))", "Traceback (most recent call last):\n  collect, called at 25\n  gen, called at 33\n"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(traceback(given), *expected, "Evaluating {}", given);
+    }
+}
+
+#[test]
+fn test_error_kinds() {
+    let kind = |source: &str| match eval(source) {
+        Err(errors) => errors[0].kind(),
+        Ok(obj) => panic!("Expected {} to be an error but got {}", source, obj),
+    };
+
+    let tests = [
+        ("\"\\q\"", ErrorKind::Lex),
+        ("(1 2)", ErrorKind::Parse),
+        ("nope", ErrorKind::Name),
+        ("1 + \"a\"", ErrorKind::Type),
+        ("(|x| x)(1, 2)", ErrorKind::Arity),
+        ("len(1, 2)", ErrorKind::Arity),
+        ("[\"a\": 1][\"b\"]", ErrorKind::Index),
+        ("1 // 0", ErrorKind::Value),
+        ("lines(\"no/such/file\") |> collect", ErrorKind::Io),
+        ("raise('bad, \"x\")", ErrorKind::Raised),
+        ("yield 1", ErrorKind::Runtime),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(kind(given), *expected, "Evaluating {}", given);
+    }
+
+    assert_eq!(ErrorKind::Name.code(), "E0003");
+}
+
+#[test]
+fn test_call_depth() {
+    // Reaching the limit shouldn't depend on how big the thread's stack is.
+    let result = thread::Builder::new().stack_size(1024 * 1024).spawn(|| {
+        let count = |n: usize| format!("f := |n| if n == 0 then 0 else 1 + f(n - 1) in f({})", n);
+        assert_eq!(eval_to_string(&count(MAX_CALL_DEPTH - 1)), (MAX_CALL_DEPTH - 1).to_string());
+
+        match eval(&count(MAX_CALL_DEPTH * 5)) {
+            Err(errors) => {
+                assert_eq!(errors[0].kind(), ErrorKind::Runtime);
+                assert_eq!(errors[0].trace().len(), MAX_CALL_DEPTH);
+            },
+            Ok(obj) => panic!("Expected deep recursion to be an error but got {}", obj),
+        }
+
+        // Each yield from that isn't the last thing a generator does nests another generator.
+        let nested = |n: usize| format!("g := |n|\n    if n > 0 then yield from g(n - 1)\n    yield n\nin len(collect(g({})))", n);
+        assert_eq!(eval_to_string(&nested(MAX_CALL_DEPTH / 2)), (MAX_CALL_DEPTH / 2 + 1).to_string());
+
+        match eval(&nested(MAX_CALL_DEPTH * 50)) {
+            Err(errors) => assert_eq!(errors[0].kind(), ErrorKind::Runtime),
+            Ok(obj) => panic!("Expected deeply nested generators to be an error but got {}", obj),
+        }
+    }).unwrap().join();
+
+    assert!(result.is_ok());
+}
@@ -1,46 +1,64 @@
-use rough::token::{ Token, TokenType };
+use rough::token::{ Token, TokenType, StrPart };
 use rough::lexer::Lexer;
+use rough::layout::layout;
 use rough::error::RoughResult;
+use rough::span::Span;
+use num_bigint::BigInt;
 
 #[test]
 fn test_simple_tokens() {
-    let tests = vec![
+    let tests = [
         ("()[],|#".to_string(), vec![
-         Token::new(TokenType::LParen, 0),
-         Token::new(TokenType::RParen, 1),
-         Token::new(TokenType::LBracket, 2),
-         Token::new(TokenType::RBracket, 3),
-         Token::new(TokenType::Comma, 4),
-         Token::new(TokenType::Pipe, 5),
-         Token::new(TokenType::Hash, 6),
+         Token::new(TokenType::LParen, 0, 1),
+         Token::new(TokenType::RParen, 1, 2),
+         Token::new(TokenType::LBracket, 2, 3),
+         Token::new(TokenType::RBracket, 3, 4),
+         Token::new(TokenType::Comma, 4, 5),
+         Token::new(TokenType::Pipe, 5, 6),
+         Token::new(TokenType::Comment(String::new()), 6, 7),
         ]),
         ("foo := |x| 53 in print
             \"bar\"".to_string(), vec![
-            Token::new(TokenType::Ident("foo".to_string()), 0),
-            Token::new(TokenType::Space, 3),
-            Token::new(TokenType::Assign, 4),
-            Token::new(TokenType::Space, 6),
-            Token::new(TokenType::Pipe, 7),
-            Token::new(TokenType::Ident("x".to_string()), 8),
-            Token::new(TokenType::Pipe, 9),
-            Token::new(TokenType::Space, 10),
-            Token::new(TokenType::Number(53.0), 11),
-            Token::new(TokenType::Space, 13),
-            Token::new(TokenType::In, 14),
-            Token::new(TokenType::Space, 16),
-            Token::new(TokenType::Ident("print".to_string()), 17),
-            Token::new(TokenType::Newline, 22),
-            Token::new(TokenType::Tab, 23),
-            Token::new(TokenType::Tab, 27),
-            Token::new(TokenType::Tab, 31),
-            Token::new(TokenType::Str("bar".to_string()), 35),
+            Token::new(TokenType::Ident("foo".to_string()), 0, 3),
+            Token::new(TokenType::Space, 3, 4),
+            Token::new(TokenType::Assign, 4, 6),
+            Token::new(TokenType::Space, 6, 7),
+            Token::new(TokenType::Pipe, 7, 8),
+            Token::new(TokenType::Ident("x".to_string()), 8, 9),
+            Token::new(TokenType::Pipe, 9, 10),
+            Token::new(TokenType::Space, 10, 11),
+            Token::new(TokenType::Integer(BigInt::from(53)), 11, 13),
+            Token::new(TokenType::Space, 13, 14),
+            Token::new(TokenType::In, 14, 16),
+            Token::new(TokenType::Space, 16, 17),
+            Token::new(TokenType::Ident("print".to_string()), 17, 22),
+            Token::new(TokenType::Newline, 22, 23),
+            Token::new(TokenType::Tab, 23, 27),
+            Token::new(TokenType::Tab, 27, 31),
+            Token::new(TokenType::Tab, 31, 35),
+            Token::new(TokenType::Str("bar".to_string()), 35, 40),
+            ]),
+        ("part2 := sha256(retry_3x)".to_string(), vec![
+            Token::new(TokenType::Ident("part2".to_string()), 0, 5),
+            Token::new(TokenType::Space, 5, 6),
+            Token::new(TokenType::Assign, 6, 8),
+            Token::new(TokenType::Space, 8, 9),
+            Token::new(TokenType::Ident("sha256".to_string()), 9, 15),
+            Token::new(TokenType::LParen, 15, 16),
+            Token::new(TokenType::Ident("retry_3x".to_string()), 16, 24),
+            Token::new(TokenType::RParen, 24, 25),
+            ]),
+        ("x1.y2".to_string(), vec![
+            Token::new(TokenType::Ident("x1".to_string()), 0, 2),
+            Token::new(TokenType::Operator(".".to_string()), 2, 3),
+            Token::new(TokenType::Ident("y2".to_string()), 3, 5),
             ]),
     ];
 
     for (test, (given, expected)) in tests.iter().enumerate() {
         let lexer = Lexer::new(given);
         let lexer_output: Vec<RoughResult<Token>> = lexer.collect();
-        let expected_result: Vec<RoughResult<Token>> = expected.into_iter()
+        let expected_result: Vec<RoughResult<Token>> = expected.iter()
             .map(|exp| Ok(exp.clone()))
             .collect();
         //assert_eq!(lexer_output, expected_result, "Test{}: {:?} not equal to {:?}", test, lexer_output, expected_result);
@@ -49,3 +67,294 @@ fn test_simple_tokens() {
         }
     }
 }
+
+fn token_types(source: &str) -> RoughResult<Vec<TokenType>> {
+    Lexer::new(source)
+        .map(|result| result.map(|token| token.token_type))
+        .filter(|result| result.as_ref().map(|token_type| *token_type != TokenType::Space).unwrap_or(true))
+        .collect()
+}
+
+fn integer(num: i64) -> TokenType {
+    TokenType::Integer(BigInt::from(num))
+}
+
+#[test]
+fn test_numbers() {
+    let tests = [
+        ("2.75", vec![TokenType::Float(2.75)]),
+        ("1_000_000", vec![integer(1_000_000)]),
+        ("1.5e3 2E-2 6e+1", vec![TokenType::Float(1500.0), TokenType::Float(0.02), TokenType::Float(60.0)]),
+        ("0xff 0o17 0b1010 0xFF_FF", vec![integer(255), integer(15), integer(10), integer(65535)]),
+        ("0xffffffffffffffffff", vec![TokenType::Integer(BigInt::parse_bytes(b"ffffffffffffffffff", 16).unwrap())]),
+        ("1..5", vec![integer(1), TokenType::Operator("..".to_string()), integer(5)]),
+        ("2.x", vec![integer(2), TokenType::Operator(".".to_string()), TokenType::Ident("x".to_string())]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(expected.clone()), "Lexing {}", given);
+    }
+}
+
+/// The first error's message and where it points.
+fn first_error(source: &str) -> (String, Option<Span>) {
+    match token_types(source) {
+        Err(errors) => (errors[0].to_string(), errors[0].span()),
+        Ok(tokens) => panic!("Expected {} to be a lexer error but got {:?}", source, tokens),
+    }
+}
+
+#[test]
+fn test_malformed_numbers() {
+    let tests = [
+        ("1__0", "Misplaced _ in number literal", Span::new(2, 3)),
+        ("1_", "Number literal can't end with _", Span::new(1, 2)),
+        ("0x", "Number literal is missing digits", Span::new(0, 2)),
+        ("0b102", "Unexpected 2 in number literal", Span::new(4, 5)),
+        ("1e", "Number literal is missing digits", Span::new(0, 2)),
+        ("1.5e+", "Number literal is missing digits", Span::new(0, 5)),
+        ("12ab", "Unexpected a in number literal", Span::new(2, 3)),
+        ("2fast", "Unexpected f in number literal", Span::new(1, 2)),
+        ("1e999", "Float literal out of range", Span::new(0, 5)),
+        ("-2.5e400", "Float literal out of range", Span::new(1, 8)),
+    ];
+
+    for (given, message, span) in tests.iter() {
+        assert_eq!(first_error(given), (message.to_string(), Some(*span)), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_string_escapes() {
+    let tests = [
+        (r#""say \"hi\"""#, "say \"hi\""),
+        (r#""a\\b""#, "a\\b"),
+        (r#""line\nnext\ttab\r\0""#, "line\nnext\ttab\r\0"),
+        (r#""\x41\x7e""#, "A~"),
+        (r#""\u{48}\u{e9}\u{1F600}""#, "H\u{e9}\u{1F600}"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(vec![TokenType::Str(expected.to_string())]), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_bad_string_escapes() {
+    let tests = [
+        (r#""\q""#, "Unknown escape \\q", Span::new(1, 3)),
+        (r#""bad \q escape""#, "Unknown escape \\q", Span::new(5, 7)),
+        (r#""ab\x4""#, "Escape should be \\x followed by two hex digits up to 7F", Span::new(3, 6)),
+        (r#""\x80""#, "Escape should be \\x followed by two hex digits up to 7F", Span::new(1, 5)),
+        (r#""\u{110000}""#, "Escape should be \\u{...} with 1 to 6 hex digits of a valid character", Span::new(1, 11)),
+        (r#""\u{}""#, "Escape should be \\u{...} with 1 to 6 hex digits of a valid character", Span::new(1, 5)),
+        (r#""\u41""#, "Escape should be \\u{...} with 1 to 6 hex digits of a valid character", Span::new(1, 3)),
+    ];
+
+    for (given, message, span) in tests.iter() {
+        assert_eq!(first_error(given), (message.to_string(), Some(*span)), "Lexing {}", given);
+    }
+
+    // Spans count from where the lexer starts, like they do for later files in a SourceMap.
+    let errors = Lexer::new(r#""\q""#).starting_at(100).find_map(Result::err).unwrap();
+    assert_eq!(errors[0].span(), Some(Span::new(101, 103)));
+
+    // Lexing carries on after the string.
+    let results: Vec<RoughResult<Token>> = Lexer::new(r#""\q" 1"#).collect();
+    assert!(results[0].is_err());
+    assert_eq!(results[2], Ok(Token::new(integer(1), 5, 6)));
+}
+
+#[test]
+fn test_interpolated_string() {
+    let expected = vec![
+        Token::new(TokenType::Interpolated(vec![
+            StrPart::Literal("a ".to_string()),
+            StrPart::Code(vec![
+                Token::new(TokenType::Ident("x".to_string()), 4, 5),
+                Token::new(TokenType::Space, 5, 6),
+                Token::new(TokenType::Operator("+".to_string()), 6, 7),
+                Token::new(TokenType::Space, 7, 8),
+                Token::new(integer(1), 8, 9),
+            ]),
+            StrPart::Literal(" b".to_string()),
+        ]), 0, 13),
+    ];
+
+    let output: Vec<RoughResult<Token>> = Lexer::new("\"a {x + 1} b\"").collect();
+    assert_eq!(output, expected.into_iter().map(Ok).collect::<Vec<RoughResult<Token>>>());
+}
+
+#[test]
+fn test_raw_strings() {
+    let tests = [
+        (r#"r"C:\path\{x}""#, r"C:\path\{x}"),
+        (r###"r#"say "hi""#"###, r#"say "hi""#),
+        (r###"r##"a "# b"##"###, r##"a "# b"##),
+        ("r\"two\nlines\"", "two\nlines"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(vec![TokenType::Str(expected.to_string())]), "Lexing {}", given);
+    }
+
+    assert_eq!(
+        token_types("r #comment"),
+        Ok(vec![TokenType::Ident("r".to_string()), TokenType::Comment("comment".to_string())])
+        );
+    assert!(token_types(r##"r#"open""##).is_err());
+}
+
+#[test]
+fn test_heredocs() {
+    let tests = [
+        ("\"\"\"one line\"\"\"", "one line"),
+        ("\"\"\"\n    first\n      second\n    third\n    \"\"\"", "first\n  second\nthird"),
+        ("\"\"\"\n        deep\n\n        after blank\n    \"\"\"", "    deep\n\n    after blank"),
+        ("\"\"\"\n\techo \"$HOME\" \\n\n\t\"\"\"", "echo \"$HOME\" \\n"),
+        ("\"\"\"\r\n  windows\r\n  \"\"\"", "windows"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(vec![TokenType::Str(expected.to_string())]), "Lexing {}", given);
+    }
+
+    assert!(token_types("\"\"\"\nnever closed\n").is_err());
+}
+
+#[test]
+fn test_comments() {
+    let tests = [
+        ("# line *# still line\n1", vec![
+         TokenType::Comment(" line *# still line".to_string()),
+         TokenType::Newline,
+         integer(1),
+        ]),
+        ("#* block *# 1", vec![TokenType::Comment(" block ".to_string()), integer(1)]),
+        ("#* a #* nested *# b *#1", vec![TokenType::Comment(" a #* nested *# b ".to_string()), integer(1)]),
+        ("#*\nmany\nlines\n*#", vec![TokenType::Comment("\nmany\nlines\n".to_string())]),
+        ("#**#", vec![TokenType::Comment(String::new())]),
+        ("## Doc comment\n", vec![TokenType::DocComment("Doc comment".to_string()), TokenType::Newline]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(expected.clone()), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_unterminated_block_comments() {
+    let tests = [
+        ("#* never closed", Span::new(0, 2)),
+        ("1 #* a #* b *#", Span::new(2, 4)),
+    ];
+
+    for (given, span) in tests.iter() {
+        assert_eq!(
+            first_error(given),
+            ("File ended before the block comment was closed".to_string(), Some(*span)),
+            "Lexing {}", given
+            );
+    }
+}
+
+#[test]
+fn test_unicode_identifiers() {
+    let tests = [
+        ("größe", vec![TokenType::Ident("größe".to_string())]),
+        ("名前 дата", vec![TokenType::Ident("名前".to_string()), TokenType::Ident("дата".to_string())]),
+        // A decomposed é is normalised to the composed one.
+        ("cafe\u{301}", vec![TokenType::Ident("caf\u{e9}".to_string())]),
+        ("नमस्ते", vec![TokenType::Ident("नमस्ते".to_string())]),
+        ("'ключ", vec![TokenType::Symbol("ключ".to_string())]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(expected.clone()), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_unicode_operators() {
+    let tests = [
+        ("f ∘ g", vec![TokenType::Ident("f".to_string()), TokenType::Operator("∘".to_string()), TokenType::Ident("g".to_string())]),
+        ("a→b", vec![TokenType::Ident("a".to_string()), TokenType::Operator("→".to_string()), TokenType::Ident("b".to_string())]),
+        ("1 ≤ 2", vec![integer(1), TokenType::Operator("≤".to_string()), integer(2)]),
+        ("-→", vec![TokenType::Operator("-→".to_string())]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(expected.clone()), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_invalid_unicode() {
+    let tests = [
+        ("x = \u{201C}hi\u{201D}", "Unexpected character \u{201C} (U+201C). Strings use plain double quotes (\").", Span::new(4, 7)),
+        ("a\u{00A0}b", "Unexpected character \\u{a0} (U+00A0). It's a non-ASCII space.", Span::new(1, 3)),
+        ("\u{200B}x", "Unexpected character \\u{200b} (U+200B). It's invisible, so it was probably pasted in by accident.", Span::new(0, 3)),
+        ("x \u{27E8}", "Unexpected character \u{27E8} (U+27E8).", Span::new(2, 5)),
+        ("\u{0663}", "Unexpected character \u{0663} (U+0663). Only ASCII digits can be used in numbers.", Span::new(0, 2)),
+    ];
+
+    for (given, message, span) in tests.iter() {
+        assert_eq!(first_error(given), (message.to_string(), Some(*span)), "Lexing {}", given);
+    }
+}
+
+#[test]
+fn test_tab_width() {
+    let lex = |source, width| Lexer::with_tab_width(source, width)
+        .map(|result| result.map(|token| token.token_type))
+        .collect::<RoughResult<Vec<TokenType>>>();
+
+    assert_eq!(lex("    x", 4), Ok(vec![TokenType::Tab, TokenType::Ident("x".to_string())]));
+    assert_eq!(lex("    x", 2), Ok(vec![TokenType::Tab, TokenType::Tab, TokenType::Ident("x".to_string())]));
+    assert_eq!(lex("   x", 4), Ok(vec![TokenType::Space, TokenType::Space, TokenType::Space, TokenType::Ident("x".to_string())]));
+}
+
+#[test]
+fn test_layout() {
+    let laid_out = |source| {
+        let tokens = Lexer::new(source).collect::<RoughResult<Vec<Token>>>()?;
+        layout(tokens, 4).map(|tokens| tokens.into_iter()
+            .map(|token| token.token_type)
+            .filter(|token_type| !matches!(token_type, TokenType::Space | TokenType::Tab | TokenType::Newline))
+            .collect::<Vec<TokenType>>())
+    };
+    let x = || TokenType::Ident("x".to_string());
+
+    let tests = [
+        ("x\nx", vec![x(), x()]),
+        ("x\n    x\nx", vec![x(), TokenType::Indent, x(), TokenType::Dedent, x()]),
+        ("x\n    x\n        x", vec![x(), TokenType::Indent, x(), TokenType::Indent, x(), TokenType::Dedent, TokenType::Dedent]),
+        ("x\n\tx\n\n    # comment\n    x", vec![x(), TokenType::Indent, x(), TokenType::Comment(" comment".to_string()), x(), TokenType::Dedent]),
+        ("x(\n    x\n)", vec![x(), TokenType::LParen, x(), TokenType::RParen]),
+        ("  x\n  x", vec![x(), x()]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(laid_out(given), Ok(expected.clone()), "Laying out {:?}", given);
+    }
+
+    for given in ["x\n    x\n  x", "  x\nx"].iter() {
+        assert!(laid_out(given).is_err(), "Expected {:?} to be a layout error", given);
+    }
+}
+
+#[test]
+fn test_pipe_forward() {
+    let x = || TokenType::Ident("x".to_string());
+    let tests = [
+        ("x |> x", vec![x(), TokenType::PipeForward, x()]),
+        ("x|>x", vec![x(), TokenType::PipeForward, x()]),
+        ("|x| x", vec![TokenType::Pipe, x(), TokenType::Pipe, x()]),
+        ("| >", vec![TokenType::Pipe, TokenType::Operator(">".to_string())]),
+        ("x |>> x", vec![x(), TokenType::PipeForward, TokenType::Operator(">".to_string()), x()]),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(token_types(given), Ok(expected.clone()), "Lexing {}", given);
+    }
+}
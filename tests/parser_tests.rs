@@ -0,0 +1,294 @@
+mod test_utils;
+
+use test_utils::parse;
+use rough::ast::{ Expression, Clause };
+use rough::span::{ Span, Spanned };
+
+/// Spans aren't compared, so expected nodes don't need them.
+fn node(exp: Expression) -> Spanned<Expression> {
+    Spanned::unspanned(exp)
+}
+
+fn ident(name: &str) -> Box<Spanned<Expression>> {
+    Box::new(node(Expression::Ident(name.to_string())))
+}
+
+#[test]
+fn test_doc_comments() {
+    let tests = [
+        ("## Doubles x.\n## Really.\ndouble := |x| x in double", node(Expression::Let(
+                "double".to_string(),
+                Box::new(node(Expression::Function(vec!["x".to_string()], ident("x"), None))),
+                ident("double"),
+                Some("Doubles x.\nReally.".to_string())
+                ))),
+        ("##Identity\n|x| x", node(Expression::Function(vec!["x".to_string()], ident("x"), Some("Identity".to_string())))),
+        ("# Not a doc comment\nf := |x| x in f", node(Expression::Let(
+                "f".to_string(),
+                Box::new(node(Expression::Function(vec!["x".to_string()], ident("x"), None))),
+                ident("f"),
+                None
+                ))),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+}
+
+#[test]
+fn test_sequences() {
+    let x = || *ident("x");
+    let y = || *ident("y");
+
+    let tests = [
+        ("x; y", node(Expression::Block(vec![x(), y()]))),
+        ("x\ny\n", node(Expression::Block(vec![x(), y()]))),
+        ("x\n\n# comment\ny", node(Expression::Block(vec![x(), y()]))),
+        ("(x\n)", x()),
+        ("x :=\n    x\n    y\nin y; x", node(Expression::Let(
+                "x".to_string(),
+                Box::new(node(Expression::Block(vec![x(), y()]))),
+                Box::new(node(Expression::Block(vec![y(), x()]))),
+                None
+                ))),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+
+    for given in ["x y", "(x) 1", "x;", "x; ;y", "x )"].iter() {
+        assert!(parse(given).is_err(), "Expected {} to be a parser error", given);
+    }
+}
+
+fn if_exp(
+    cond: Box<Spanned<Expression>>,
+    cons: Box<Spanned<Expression>>,
+    alt: Option<Box<Spanned<Expression>>>
+    ) -> Spanned<Expression> {
+    node(Expression::If(cond, cons, alt))
+}
+
+#[test]
+fn test_if_expressions() {
+    let tests = [
+        ("if a then b", if_exp(ident("a"), ident("b"), None)),
+        ("if a then b else c", if_exp(ident("a"), ident("b"), Some(ident("c")))),
+        ("if a\n    b\nelse\n    c", if_exp(ident("a"), ident("b"), Some(ident("c")))),
+        ("if a then\n    b\nelse c", if_exp(ident("a"), ident("b"), Some(ident("c")))),
+        // The else goes with the closest if.
+        ("if a then if b then c else d", if_exp(
+                ident("a"),
+                Box::new(if_exp(ident("b"), ident("c"), Some(ident("d")))),
+                None
+                )),
+        // Unless layout says otherwise.
+        ("if a\n    if b then c\nelse d", if_exp(
+                ident("a"),
+                Box::new(if_exp(ident("b"), ident("c"), None)),
+                Some(ident("d"))
+                )),
+        ("if a then b\nelse if c then d\nelse e", if_exp(
+                ident("a"),
+                ident("b"),
+                Some(Box::new(if_exp(ident("c"), ident("d"), Some(ident("e")))))
+                )),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+
+    // The condition always runs up to the then, whatever it looks like.
+    let same = [
+        ("if f (x) then y", "if (f(x)) then y"),
+        ("if x -1 then y", "if (x - 1) then y"),
+        ("if m [k] then y", "if (m[k]) then y"),
+        ("if a then b + 1 else c", "if a then (b + 1) else c"),
+    ];
+
+    for (given, expected) in same.iter() {
+        assert_eq!(parse(given), parse(expected), "Parsing {}", given);
+    }
+
+    for given in ["if a b", "if a", "if a then", "if a then b else", "else b"].iter() {
+        assert!(parse(given).is_err(), "Expected {} to be a parser error", given);
+    }
+}
+
+#[test]
+fn test_cond_expressions() {
+    let tests = [
+        ("cond\n    a then b\n    c then d\n    else e", "if a then b else if c then d else e"),
+        ("cond\n    a then b; c then d", "if a then b else if c then d"),
+        ("cond\n    a\n        b\n    else\n        c", "if a then b else c"),
+        ("cond\n    else e", "e"),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), parse(expected), "Parsing {}", given);
+    }
+
+    for given in ["cond a then b", "cond\n    a b", "cond\n    else a\n    b then c"].iter() {
+        assert!(parse(given).is_err(), "Expected {} to be a parser error", given);
+    }
+}
+
+#[test]
+fn test_yield_expressions() {
+    let tests = [
+        ("yield x", node(Expression::Yield(ident("x")))),
+        ("yield from x", node(Expression::YieldFrom(ident("x")))),
+        ("yield x + 1", parse("yield (x + 1)").unwrap()),
+        ("|x| yield x", node(Expression::Function(vec!["x".to_string()], Box::new(node(Expression::Yield(ident("x")))), None))),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+
+    assert!(parse("yield").is_err());
+}
+
+#[test]
+fn test_comprehensions() {
+    let tests = [
+        ("[x for x in xs]", node(Expression::Comprehension(
+                None,
+                ident("x"),
+                vec![Clause::For(vec!["x".to_string()], *ident("xs"))]
+                ))),
+        ("[k: v for k, v in m if v]", node(Expression::Comprehension(
+                Some(ident("k")),
+                ident("v"),
+                vec![Clause::For(vec!["k".to_string(), "v".to_string()], *ident("m")), Clause::If(*ident("v"))]
+                ))),
+        ("[x for x in xs for y in x]", node(Expression::Comprehension(
+                None,
+                ident("x"),
+                vec![Clause::For(vec!["x".to_string()], *ident("xs")), Clause::For(vec!["y".to_string()], *ident("x"))]
+                ))),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+
+    for given in ["[x for x xs]", "[x for 1 in xs]", "[x, y for x in xs]", "[x for x in xs, y]", "[x if y]", "[x for x in (yield xs)]"].iter() {
+        assert!(parse(given).is_err(), "Expected {} to be a parser error", given);
+    }
+
+    // A yield can't stop a comprehension part way, but one in a function inside it is fine.
+    match parse("g := |xs| [yield x for x in xs] in g") {
+        Err(errors) => {
+            assert_eq!(errors[0].message(), "yield can't be used inside a comprehension");
+            assert_eq!(errors[0].span(), Some(Span::new(11, 18)));
+        },
+        Ok(exp) => panic!("Expected a yield in a comprehension to be a parser error but got {:?}", exp),
+    }
+    assert!(parse("[|x| yield x for x in xs]").is_ok());
+}
+
+#[test]
+fn test_try_expressions() {
+    let tests = [
+        ("try a catch e b", node(Expression::Try(ident("a"), "e".to_string(), ident("b")))),
+        ("try\n    a\ncatch e\n    b", node(Expression::Try(ident("a"), "e".to_string(), ident("b")))),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given), Ok(expected.clone()), "Parsing {}", given);
+    }
+
+    for given in ["try a", "try a catch b", "try a catch 1 b", "catch e b"].iter() {
+        assert!(parse(given).is_err(), "Expected {} to be a parser error", given);
+    }
+}
+
+/// Spans are left out of equality, so they're checked on their own here.
+#[test]
+fn test_spans() {
+    let exp = parse("f(1 + 2, x)[0]").unwrap();
+    assert_eq!(exp.span, Span::new(0, 14));
+
+    let call = match &exp.node {
+        Expression::Index(left, index) => {
+            assert_eq!(index.span, Span::new(12, 13));
+            left
+        },
+        other => panic!("Expected an index but got {:?}", other),
+    };
+    assert_eq!(call.span, Span::new(0, 11));
+
+    match &call.node {
+        Expression::Call(function, args) => {
+            assert_eq!(function.span, Span::new(0, 1));
+            assert_eq!(args[0].span, Span::new(2, 7));
+            assert_eq!(args[1].span, Span::new(9, 10));
+        },
+        other => panic!("Expected a call but got {:?}", other),
+    }
+
+    let tests = [
+        ("  (x)", Span::new(2, 5)),
+        ("x := 1 in\nx", Span::new(0, 11)),
+        ("x; y", Span::new(0, 4)),
+        ("if a\n    b\nelse c", Span::new(0, 17)),
+        ("|x| x # comment", Span::new(0, 5)),
+        ("\"a {x} b\"", Span::new(0, 9)),
+        ("x |> f", Span::new(0, 6)),
+        ("-x", Span::new(0, 2)),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(parse(given).map(|exp| exp.span), Ok(*expected), "Parsing {:?}", given);
+    }
+}
+
+#[test]
+fn test_error_recovery() {
+    let messages = |source: &str| match parse(source) {
+        Err(errors) => errors.iter().map(|error| error.message().to_string()).collect::<Vec<String>>(),
+        Ok(exp) => panic!("Expected {} to be a parser error but got {:?}", source, exp),
+    };
+
+    let source = "a := (1 2) in\nb := [1, 2 3] in\nc := f(1,, 2) in\nd := 4 + in\ne := |x x in\n[a, b, c, d, e]";
+    assert_eq!(messages(source), vec![
+        "Expected next token to be ) but it was 2",
+        "Expected next token to be ] but it was 3",
+        "Expected an expression but got ,",
+        "Expected an expression but got in",
+        "Expected next token to be | but it was x",
+    ]);
+
+    let tests = [
+        ("f(1 2)\ng(-)", 2),
+        ("x +; y +", 2),
+        ("f(1))\n2 +", 2),
+        ("if x then\n", 1),
+        ("[x, y for x in xs]", 1),
+        // Recovery skips the rest of the line after an error, but not the lines after it.
+        ("if 1 2\nprint(3 +)\nprint(4 +)", 3),
+        ("x := 1 in\nif x 2\nprint(x +)\nprint(x *)", 3),
+        ("a b c\nd e", 2),
+        // Errors from reading the tokens are reported along with the ones from parsing them.
+        ("print(\"a\\q\")\nprint(1 +)", 2),
+        ("\"\\q\"", 1),
+        ("x := 1 in\n\"bad \\q\"", 1),
+        ("f(\"\\q\", 1 2)", 2),
+        ("f := |x|\n    x +\n    y *\nin f(1 2)", 2),
+        ("f := |x|\n    x + 1 2\n    x +\nin f(1 2)", 2),
+        // A closing bracket that already ended something isn't reported again.
+        ("x := f(1) 2", 1),
+        ("x := [1] y", 1),
+        ("foo(]", 1),
+        ("[f(1]", 1),
+        ("f(1 2))", 2),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(messages(given).len(), *expected, "Parsing {:?} gave {:?}", given, messages(given));
+    }
+}
@@ -0,0 +1,30 @@
+use rough::repl;
+use std::io::Cursor;
+
+fn run_repl(input: &str) -> String {
+    let mut output = vec![];
+    repl::start(Cursor::new(input), &mut output, false).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_repl() {
+    let tests = [
+        ("1 + 2\n", ">> 3\n>> "),
+        ("nope\n", ">> Error[E0003]: Identifier nope not found\n --> <repl>:1:1\n  |\n1 | nope\n  | ^^^^\n>> "),
+        ("f := |x| nope in f(1)\n", ">> Traceback (most recent call last):\n  f, called at <repl>:1:18\nError[E0003]: Identifier nope not found\n --> <repl>:1:10\n  |\n1 | f := |x| nope in f(1)\n  |          ^^^^\n>> "),
+        (":help len\n", ">> len (builtin)\nThe number of characters in a string, entries in an IndexMap or values in a range.\n>> "),
+        // A doc comment isn't finished on its own, so the function on the next line goes with it.
+        (":help ## Says hi.\n|name| \"hi {name}\"\n", ">> .. |name|\nSays hi.\n>> "),
+        (":help ## Says hi.\ngreet := |name| \"hi {name}\"\nin greet\n", ">> .. .. |name|\nSays hi.\n>> "),
+        ("f(1,\n  2)\n", ">> .. Error[E0003]: Identifier f not found\n --> <repl>:1:1\n  |\n1 | f(1,\n  | ^\n>> "),
+        // A blank line gives up on unfinished code.
+        ("[1 +\n\n2\n", ">> .. Error[E0002]: Source ended before making a valid expression\n --> <repl>:1:5\n  |\n1 | [1 +\n  |     ^\nError[E0002]: Expected next token to be ] but the source ended\n --> <repl>:1:5\n  |\n1 | [1 +\n  |     ^\n ::: <repl>:1:1\n  |\n1 | [1 +\n  | - opened here\n>> 2\n>> "),
+        (":bogus\n", ">> Unknown command :bogus. Try :help\n>> "),
+        (":quit\n1\n", ">> "),
+    ];
+
+    for (given, expected) in tests.iter() {
+        assert_eq!(run_repl(given), *expected, "REPL input {}", given);
+    }
+}
@@ -0,0 +1,29 @@
+#![allow(dead_code)]
+
+use rough::lexer::Lexer;
+use rough::parser::Parser;
+use rough::evaluator::evaluate;
+use rough::environment::Environment;
+use rough::builtin::operators;
+use rough::ast::Expression;
+use rough::span::Spanned;
+use rough::object::Object;
+use rough::error::RoughResult;
+
+pub fn parse(source: &str) -> RoughResult<Spanned<Expression>> {
+    let mut parser = Parser::new(Lexer::new(source), operators());
+    parser.parse_program()
+}
+
+pub fn eval(source: &str) -> RoughResult<Object> {
+    let exp = parse(source)?;
+    evaluate(&exp, &Environment::global())
+}
+
+/// Evaluates and displays the result so tests can compare against a string.
+pub fn eval_to_string(source: &str) -> String {
+    match eval(source) {
+        Ok(obj) => obj.to_string(),
+        Err(errors) => panic!("{} gave errors: {:?}", source, errors),
+    }
+}